@@ -1,6 +1,6 @@
 use game::{CUBE_H, CUBE_W, GRID_W, GRID_H, HZ, HZ_BOTTOM, CameraX, CameraY, Cell, Grid, GridX, GridY, grid_xy_to_i, GridInner, GridXInner, GridYInner, MoveMode, X_SCALE, Y_SCALE};
-use gfx::{Commands};
-use platform_types::{command, sprite, unscaled, Button, Input, Speaker, SFX};
+use gfx::{Commands, Layer};
+use platform_types::{command::{self, Alpha}, sprites, unscaled, Button, Input, Requests, Speaker, SFX};
 pub use platform_types::StateParams;
 
 pub struct State {
@@ -8,6 +8,18 @@ pub struct State {
     pub commands: Commands,
     pub input: Input,
     pub speaker: Speaker,
+    /// The resolution the commands are drawn at. Set this before passing
+    /// the state to the platform, to use something other than the default.
+    pub logical_size: command::Size,
+    pub settings: Settings,
+    scenes: Scenes,
+    /// Used to make a new `game_state` when starting over.
+    seed: [u8; 16],
+    /// Whether the sounds in `speaker` were returned from `render` already,
+    /// so the next update should start over with none.
+    sounds_taken: bool,
+    /// What to ask the platform to do, next time it asks.
+    requests: Requests,
 }
 
 impl State {
@@ -29,43 +41,378 @@ impl State {
             commands: Commands::default(),
             input: Input::default(),
             speaker: Speaker::default(),
+            logical_size: command::Size::default(),
+            settings: Settings::default(),
+            scenes: Scenes::default(),
+            seed,
+            sounds_taken: false,
+            requests: Requests::default(),
         }
     }
 }
 
 impl platform_types::State for State {
-    fn frame(&mut self) -> (&[platform_types::Command], &[SFX]) {
+    fn update(&mut self) {
         self.commands.clear();
-        self.speaker.clear();
-        update_and_render(
-            &mut self.commands,
-            &mut self.game_state,
+        self.commands.set_logical_size(self.logical_size);
+        if self.sounds_taken {
+            self.speaker.clear();
+            self.sounds_taken = false;
+        }
+
+        self.scenes.frame(
+            &mut Context {
+                commands: &mut self.commands,
+                game_state: &mut self.game_state,
+                speaker: &mut self.speaker,
+                settings: &mut self.settings,
+                seed: self.seed,
+                requests: &mut self.requests,
+                logical_size: self.logical_size,
+            },
             self.input,
-            &mut self.speaker,
         );
 
-        self.input.previous_gamepad = self.input.gamepad;
+        if !self.settings.sound {
+            self.speaker.clear();
+        }
+
+        self.input.end_frame();
+    }
+
+    // Everything moves in whole steps, so there is nothing to interpolate.
+    fn render(&mut self, _interpolation: f32) -> (&[platform_types::Command], &[SFX]) {
+        self.sounds_taken = true;
 
         (self.commands.slice(), self.speaker.slice())
     }
 
+    // Any key repeat from the OS is ignored, since `Input` does its own.
     fn press(&mut self, button: Button) {
-        if self.input.previous_gamepad.contains(button) {
-            //This is meant to pass along the key repeat, if any.
-            //Not sure if rewriting history is the best way to do this.
-            self.input.previous_gamepad.remove(button);
-        }
-
         self.input.gamepad.insert(button);
     }
 
     fn release(&mut self, button: Button) {
         self.input.gamepad.remove(button);
     }
+
+    fn focus_lost(&mut self) {
+        // We won't hear about these being released, so release them now.
+        self.input.gamepad = Button::default();
+        self.scenes.pause();
+    }
+
+    fn logical_size(&self) -> command::Size {
+        self.logical_size
+    }
+
+    fn take_requests(&mut self) -> Requests {
+        core::mem::take(&mut self.requests)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    pub sound: bool,
+    /// Whether to draw the debug values over the game.
+    pub debug_text: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            sound: true,
+            debug_text: true,
+        }
+    }
+}
+
+/// What scenes can see and change, besides their own state.
+struct Context<'a> {
+    commands: &'a mut Commands,
+    game_state: &'a mut game::State,
+    speaker: &'a mut Speaker,
+    settings: &'a mut Settings,
+    seed: [u8; 16],
+    requests: &'a mut Requests,
+    /// The resolution the commands are drawn at.
+    logical_size: command::Size,
+}
+
+/// A screen of the game, with whatever state only it needs.
+#[derive(Clone, Copy, Debug)]
+enum Scene {
+    Title(ui::Focus),
+    Playing,
+    Paused(ui::Focus),
+    Options(ui::Focus),
+    Summit(ui::Focus),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Transition {
+    Push(Scene),
+    Pop,
+    /// Replace every scene on the stack with this one.
+    Reset(Scene),
+}
+
+impl Scene {
+    /// Overlays are drawn on top of the scene under them, instead of
+    /// replacing it, and come and go without a fade.
+    fn is_overlay(&self) -> bool {
+        matches!(self, Scene::Paused(_))
+    }
+
+    /// Updates and draws the scene. Scenes that are covered by an overlay,
+    /// or are mid fade, get passed `Input::default()`, so they are drawn
+    /// as usual, but don't react to anything. Menus are immediate mode, so
+    /// updating and drawing them happen together.
+    fn frame(&mut self, ctx: &mut Context, input: Input) -> Option<Transition> {
+        use Transition::*;
+
+        let menu_at = |x, y| unscaled::XY { x: unscaled::X(x), y: unscaled::Y(y) };
+
+        match self {
+            Scene::Title(focus) => {
+                ctx.commands.set_layer(Layer::Ui);
+
+                let mut ui = ui::Ui::new(ctx.commands, input, focus, menu_at(200, 120), <_>::default());
+                ui.label("peak");
+                ui.space(unscaled::H(8));
+
+                if ui.button("start") {
+                    *ctx.game_state = game::State::new(ctx.seed);
+                    return Some(Reset(Scene::Playing))
+                }
+                if ui.button("options") {
+                    return Some(Push(Scene::Options(<_>::default())))
+                }
+                if ui.button("quit") {
+                    ctx.requests.quit = true;
+                }
+
+                None
+            }
+            Scene::Playing => {
+                if input.pressed_this_frame(Button::START) {
+                    return Some(Push(Scene::Paused(<_>::default())))
+                }
+
+                let was_at_summit = ctx.game_state.is_at_summit();
+
+                update(ctx.game_state, input, ctx.speaker);
+                render(ctx.commands, ctx.game_state, ctx.settings, ctx.logical_size);
+
+                if !was_at_summit && ctx.game_state.is_at_summit() {
+                    return Some(Push(Scene::Summit(<_>::default())))
+                }
+
+                None
+            }
+            Scene::Paused(focus) => {
+                ctx.commands.set_layer(Layer::Ui);
+                ctx.commands.fill_rect_with(full_screen(ctx.logical_size), BLACK_INDEX, Alpha(160));
+
+                if input.pressed_this_frame(Button::START) {
+                    return Some(Pop)
+                }
+
+                let mut ui = ui::Ui::new(ctx.commands, input, focus, menu_at(200, 120), <_>::default());
+                ui.label("paused");
+                ui.space(unscaled::H(8));
+
+                if ui.button("resume") || ui.back() {
+                    return Some(Pop)
+                }
+                if ui.button("restart") {
+                    *ctx.game_state = game::State::new(ctx.seed);
+                    return Some(Reset(Scene::Playing))
+                }
+                if ui.button("options") {
+                    return Some(Push(Scene::Options(<_>::default())))
+                }
+                if ui.button("title") {
+                    return Some(Reset(Scene::Title(<_>::default())))
+                }
+                if ui.button("quit") {
+                    ctx.requests.quit = true;
+                }
+
+                None
+            }
+            Scene::Options(focus) => {
+                ctx.commands.set_layer(Layer::Ui);
+
+                let mut ui = ui::Ui::new(ctx.commands, input, focus, menu_at(180, 120), <_>::default());
+                ui.label("options");
+                ui.space(unscaled::H(8));
+
+                ui.toggle("sound", &mut ctx.settings.sound);
+                ui.toggle("debug text", &mut ctx.settings.debug_text);
+                if ui.button("fullscreen") {
+                    ctx.requests.toggle_fullscreen = true;
+                }
+
+                if ui.button("back") || ui.back() {
+                    return Some(Pop)
+                }
+
+                None
+            }
+            Scene::Summit(focus) => {
+                ctx.commands.set_layer(Layer::Ui);
+
+                let mut ui = ui::Ui::new(ctx.commands, input, focus, menu_at(160, 120), <_>::default());
+                ui.label("you reached the summit!");
+                ui.space(unscaled::H(8));
+
+                if ui.button("keep climbing") || ui.back() {
+                    return Some(Pop)
+                }
+                if ui.button("title") {
+                    return Some(Reset(Scene::Title(<_>::default())))
+                }
+
+                None
+            }
+        }
+    }
+}
+
+/// How many frames fading out, or back in, takes.
+const FADE_FRAMES: u8 = 12;
+
+#[derive(Clone, Copy, Debug)]
+enum Fade {
+    /// How many frames into fading out we are, and what to do once the
+    /// screen is black.
+    Out(u8, Transition),
+    /// How many frames of fading in are left.
+    In(u8),
+}
+
+impl Fade {
+    fn alpha(self) -> Alpha {
+        let frames = match self {
+            Fade::Out(frames, _) | Fade::In(frames) => frames,
+        };
+
+        Alpha((u16::from(frames) * 255 / u16::from(FADE_FRAMES)) as u8)
+    }
+}
+
+struct Scenes {
+    /// The last scene is the one on top, which gets the input. Never empty.
+    stack: Vec<Scene>,
+    fade: Option<Fade>,
+}
+
+impl Default for Scenes {
+    fn default() -> Self {
+        Self {
+            stack: vec![Scene::Title(<_>::default())],
+            fade: None,
+        }
+    }
+}
+
+impl Scenes {
+    fn frame(&mut self, ctx: &mut Context, input: Input) {
+        let top = self.stack.len() - 1;
+
+        // Draw from the highest scene that is not an overlay upwards.
+        let bottom = self.stack.iter()
+            .rposition(|scene| !scene.is_overlay())
+            .unwrap_or(0);
+        for scene in &mut self.stack[bottom..top] {
+            scene.frame(ctx, Input::default());
+        }
+
+        let top_input = if self.fade.is_some() {
+            Input::default()
+        } else {
+            input
+        };
+        let transition = self.stack[top].frame(ctx, top_input);
+
+        self.fade = match (self.fade, transition) {
+            (None, Some(transition)) => {
+                if self.is_instant(transition) {
+                    self.apply(transition);
+                    None
+                } else {
+                    Some(Fade::Out(0, transition))
+                }
+            }
+            (None, None) => None,
+            (Some(Fade::Out(frames, transition)), _) => {
+                if frames >= FADE_FRAMES {
+                    self.apply(transition);
+                    Some(Fade::In(FADE_FRAMES))
+                } else {
+                    Some(Fade::Out(frames + 1, transition))
+                }
+            }
+            (Some(Fade::In(frames)), _) => {
+                frames.checked_sub(1).map(Fade::In)
+            }
+        };
+
+        if let Some(fade) = self.fade {
+            ctx.commands.set_layer(Layer::Transition);
+            ctx.commands.fill_rect_with(full_screen(ctx.logical_size), BLACK_INDEX, fade.alpha());
+        }
+    }
+
+    /// Pauses the game, if it is being played, and not already paused, or
+    /// on its way to another scene.
+    fn pause(&mut self) {
+        if self.fade.is_none() && matches!(self.stack.last(), Some(Scene::Playing)) {
+            self.apply(Transition::Push(Scene::Paused(<_>::default())));
+        }
+    }
+
+    /// Overlays are pushed and popped without fading.
+    fn is_instant(&self, transition: Transition) -> bool {
+        match transition {
+            Transition::Push(scene) => scene.is_overlay(),
+            Transition::Pop => self.stack.last().is_some_and(Scene::is_overlay),
+            Transition::Reset(_) => false,
+        }
+    }
+
+    fn apply(&mut self, transition: Transition) {
+        match transition {
+            Transition::Push(scene) => self.stack.push(scene),
+            Transition::Pop => {
+                if self.stack.len() > 1 {
+                    self.stack.pop();
+                }
+            }
+            Transition::Reset(scene) => {
+                self.stack.clear();
+                self.stack.push(scene);
+            }
+        }
+    }
+}
+
+const BLACK_INDEX: platform_types::PaletteIndex = 7;
+
+/// Covers the whole of a frame of the given size.
+fn full_screen(logical_size: command::Size) -> unscaled::Rect {
+    unscaled::Rect {
+        x: unscaled::X(0),
+        y: unscaled::Y(0),
+        w: logical_size.w(),
+        h: logical_size.h(),
+    }
 }
 
 const DEBUG_PRINTING: usize = 13;
 const DEBUG_MODE: usize = 14;
+const DEBUG_MODE_COUNT: u8 = 3;
 const DEBUG_I: usize = 15;
 const DEBUG_GRID_X_START: usize = 0;
 const DEBUG_GRID_Y_START: usize = 1;
@@ -75,16 +422,11 @@ const DEBUG_Z1: usize = 4;
 const DEBUG_Z2: usize = 5;
 
 fn update(state: &mut game::State, input: Input, speaker: &mut Speaker) {
-    let pressed = input.button_pressed_this_frame();
+    let pressed = input.button_pressed_or_repeated_this_frame();
 
-    match pressed {
-        Some(Button::START) => {
-            state.debug[DEBUG_MODE] = state.debug[DEBUG_MODE].wrapping_sub(1);
-        }
-        Some(Button::SELECT) => {
-            state.debug[DEBUG_MODE] = state.debug[DEBUG_MODE].wrapping_add(1);
-        }
-        None | _ => {}
+    // `Button::START` pauses, so this cycles through the modes.
+    if input.button_pressed_this_frame() == Some(Button::SELECT) {
+        state.debug[DEBUG_MODE] = state.debug[DEBUG_MODE].wrapping_add(1) % DEBUG_MODE_COUNT;
     }
 
     match state.debug[DEBUG_MODE] {
@@ -391,7 +733,12 @@ impl Iterator for DrawIter<'_> {
 }
 
 #[inline]
-fn render(commands: &mut Commands, state: &game::State) {
+fn render(
+    commands: &mut Commands,
+    state: &game::State,
+    settings: &Settings,
+    logical_size: command::Size,
+) {
     const BASE_X: unscaled::X = unscaled::X(0);
     const BASE_Y: unscaled::Y = unscaled::Y(0);
 
@@ -452,6 +799,7 @@ fn render(commands: &mut Commands, state: &game::State) {
         );
 
         commands.sspr(
+            sprites::cube::SHEET,
             game::CUBE_XYS[usize::from(cell.cube_i)],
             unscaled::Rect {
                 x: BASE_X + unscaled::W(
@@ -479,6 +827,7 @@ fn render(commands: &mut Commands, state: &game::State) {
         );
     
         commands.sspr(
+            sprites::player::SHEET,
             state.player.sub_face.sprite_xy(),
             unscaled::Rect {
                 x: BASE_X + unscaled::W(
@@ -499,8 +848,14 @@ fn render(commands: &mut Commands, state: &game::State) {
         );
     }
 
+    if !settings.debug_text {
+        return
+    }
+
+    commands.set_layer(Layer::Debug);
+
     commands.print_line(
-        format!("{:?}", state.debug).as_bytes(),
+        &format!("{:?}", state.debug),
         unscaled::X(0),
         unscaled::Y(0),
         6
@@ -514,37 +869,28 @@ fn render(commands: &mut Commands, state: &game::State) {
             y += unscaled::H(16);
 
             commands.print_line(
-                format!("p offsets {:?}", (player_x_offset.get(), player_y_offset.get())).as_bytes(),
+                &format!("p offsets {:?}", (player_x_offset.get(), player_y_offset.get())),
                 unscaled::X(0),
                 y,
                 6
             );
         }
         _ => {
-            let mut y = unscaled::Y(0);
-        
-            const FITS_ON_SCREEN: usize = 6;
-            for grid_slice in state.grid.chunks(FITS_ON_SCREEN) {
-                y += unscaled::H(16);
-        
-                commands.print_line(
-                    format!("{grid_slice:?}").as_bytes(),
-                    unscaled::X(0),
+            let y = unscaled::Y(16);
+
+            commands.print_in(
+                &format!("{:?}", state.grid),
+                unscaled::Rect {
+                    x: unscaled::X(0),
                     y,
-                    6
-                );
-            }
+                    w: unscaled::W(logical_size.width()),
+                    h: unscaled::H(logical_size.height()) - (y - unscaled::Y(0)),
+                },
+                gfx::text::Style {
+                    colour: 6,
+                    ..<_>::default()
+                },
+            );
         }
     }
 }
-
-#[inline]
-fn update_and_render(
-    commands: &mut Commands,
-    state: &mut game::State,
-    input: Input,
-    speaker: &mut Speaker,
-) {
-    update(state, input, speaker);
-    render(commands, state);
-}
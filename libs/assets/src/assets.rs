@@ -1,4 +1,6 @@
-use platform_types::{ARGB, FONT_LENGTH, GFX_LENGTH};
+use platform_types::{ARGB, PaletteIndex, palette, sprite::SheetId};
+
+use std::sync::OnceLock;
 
 // reportedly colourblind friendly colours
 // https://twitter.com/ea_accessible/status/968595073184092160
@@ -43,4 +45,426 @@ pub const PALETTE: [u32; 8] = [
     all instances of `index`, leaving just the indices. Format further as needed.
 */
 
-pub const GFX: [ARGB; GFX_LENGTH] = include!("gfx.in");
+/// `assets/gfx.png`, compressed by `tooling/update_gfx`.
+static GFX_BIN: &[u8] = include_bytes!("gfx.bin");
+
+/// `assets/font.png`, compressed by `tooling/update_gfx`.
+static FONT_BIN: &[u8] = include_bytes!("font.bin");
+
+fn decode_once(
+    cell: &'static OnceLock<OwnedSheet>,
+    name: &str,
+    bytes: &[u8],
+) -> &'static OwnedSheet {
+    cell.get_or_init(|| match decode(bytes) {
+        Ok(sheet) => sheet,
+        // The tests check this doesn't happen.
+        Err(e) => panic!("{name} could not be decoded: {e:?}"),
+    })
+}
+
+/// The pixels of `GFX_BIN`, decoded the first time they are needed.
+fn gfx() -> &'static OwnedSheet {
+    static GFX: OnceLock<OwnedSheet> = OnceLock::new();
+
+    decode_once(&GFX, "gfx.bin", GFX_BIN)
+}
+
+/// The pixels of `FONT_BIN`, decoded the first time they are needed.
+fn font() -> &'static OwnedSheet {
+    static FONT: OnceLock<OwnedSheet> = OnceLock::new();
+
+    decode_once(&FONT, "font.bin", FONT_BIN)
+}
+
+/// A sprite sheet: an image that `sprite::XY`s are positions in.
+pub struct Sheet {
+    pub width: usize,
+    pub height: usize,
+    /// `width * height` pixels, row by row.
+    pub pixels: &'static [ARGB],
+    /// The `palette::nearest` index of each of the `pixels`.
+    pub palette_indices: &'static [PaletteIndex],
+    /// The image file the pixels came from, relative to the `assets` folder
+    /// at the root of the repo.
+    pub source: &'static str,
+}
+
+/// A sprite sheet that was made at runtime, to be used instead of one of
+/// the `SHEETS`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OwnedSheet {
+    pub width: usize,
+    pub height: usize,
+    /// `width * height` pixels, row by row.
+    pub pixels: Vec<ARGB>,
+    /// The `palette::nearest` index of each of the `pixels`.
+    pub palette_indices: Vec<PaletteIndex>,
+}
+
+impl OwnedSheet {
+    /// Finds the `palette_indices` for `pixels`.
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<ARGB>) -> Self {
+        let palette_indices = pixels.iter()
+            .map(|&pixel| palette::nearest(pixel))
+            .collect();
+
+        Self {
+            width,
+            height,
+            pixels,
+            palette_indices,
+        }
+    }
+}
+
+macro_rules! owned_sheet {
+    ($owned: expr, $source: literal) => {
+        Sheet {
+            width: $owned.width,
+            height: $owned.height,
+            pixels: &$owned.pixels,
+            palette_indices: &$owned.palette_indices,
+            source: $source,
+        }
+    }
+}
+
+/// Indexed by `SheetId`. Each sheet can come from its own file. The font
+/// has its own, and the rest of the art is still in `gfx.png`. Splitting
+/// another one out means adding its file to the list in
+/// `tooling/update_gfx`, then pointing its entry here at the result.
+fn sheets() -> &'static [Sheet; SheetId::COUNT] {
+    static SHEETS: OnceLock<[Sheet; SheetId::COUNT]> = OnceLock::new();
+
+    SHEETS.get_or_init(|| {
+        let gfx = gfx();
+        let font = font();
+
+        [
+            // SheetId::Tiles
+            owned_sheet!(gfx, "gfx.png"),
+            // SheetId::Characters
+            owned_sheet!(gfx, "gfx.png"),
+            // SheetId::Ui
+            owned_sheet!(gfx, "gfx.png"),
+            // SheetId::Font
+            owned_sheet!(font, "font.png"),
+        ]
+    })
+}
+
+pub fn sheet(id: SheetId) -> &'static Sheet {
+    &sheets()[id as usize]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    WrongMagic,
+    UnexpectedEnd,
+    IndexOutOfRange(u16),
+    TooManyPixels,
+}
+
+const MAGIC: &[u8; 4] = b"GFX2";
+
+/// Decodes an image in the format `tooling/update_gfx` writes. All the
+/// numbers are little endian. It goes:
+/// * The bytes `GFX2`.
+/// * The width and height, as `u16`s.
+/// * The number of colours in the palette, as a `u16`, then the colours.
+///   Each one is an `ARGB` `u32`, then the `palette::nearest` index of that
+///   colour, as a `u8`.
+/// * Runs of pixels, row by row, until the image is full. Each run is its
+///   length, as an LEB128 number, then the palette index of its colour. The
+///   index is a `u8` if the palette has 256 colours or less, and a `u16`
+///   otherwise.
+///
+/// Most of the sheet is large runs of the same few colours, so this is much
+/// smaller than the raw pixels.
+pub fn decode(bytes: &[u8]) -> Result<OwnedSheet, DecodeError> {
+    use DecodeError::*;
+
+    let mut rest = bytes.strip_prefix(MAGIC).ok_or(WrongMagic)?;
+
+    macro_rules! take {
+        ($n: literal) => ({
+            if rest.len() < $n {
+                return Err(UnexpectedEnd);
+            }
+            let (taken, remaining) = rest.split_at($n);
+            rest = remaining;
+
+            let mut array = [0; $n];
+            array.copy_from_slice(taken);
+            array
+        })
+    }
+
+    let width = usize::from(u16::from_le_bytes(take!(2)));
+    let height = usize::from(u16::from_le_bytes(take!(2)));
+    let palette_len = usize::from(u16::from_le_bytes(take!(2)));
+
+    let mut palette = Vec::with_capacity(palette_len);
+    for _ in 0..palette_len {
+        let colour = ARGB::from_le_bytes(take!(4));
+        let [palette_index] = take!(1);
+        palette.push((colour, palette_index));
+    }
+
+    let wide_indices = palette_len > 256;
+
+    let length = width * height;
+    let mut pixels = Vec::with_capacity(length);
+    let mut palette_indices = Vec::with_capacity(length);
+    while pixels.len() < length {
+        let mut run_length = 0usize;
+        let mut shift = 0;
+        loop {
+            let [byte] = take!(1);
+            run_length |= usize::from(byte & 0x7F)
+                .checked_shl(shift)
+                .ok_or(TooManyPixels)?;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                break
+            }
+        }
+
+        let index = if wide_indices {
+            u16::from_le_bytes(take!(2))
+        } else {
+            u16::from(take!(1)[0])
+        };
+
+        let (colour, palette_index) = *palette.get(usize::from(index))
+            .ok_or(IndexOutOfRange(index))?;
+
+        if run_length > length - pixels.len() {
+            return Err(TooManyPixels);
+        }
+
+        pixels.resize(pixels.len() + run_length, colour);
+        palette_indices.resize(pixels.len(), palette_index);
+    }
+
+    Ok(OwnedSheet {
+        width,
+        height,
+        pixels,
+        palette_indices,
+    })
+}
+
+#[test]
+fn decode_handles_long_runs_and_wide_indices() {
+    let mut bytes = MAGIC.to_vec();
+    // 20 by 10
+    bytes.extend_from_slice(&[20, 0, 10, 0]);
+    // 257 colours, so the indices take two bytes.
+    bytes.extend_from_slice(&257u16.to_le_bytes());
+    for colour in 0..257u32 {
+        bytes.extend_from_slice(&(0xFF00_0000 | colour).to_le_bytes());
+        bytes.push(colour as u8 % 8);
+    }
+    // 150 pixels of colour 256, which needs two bytes for the length.
+    bytes.extend_from_slice(&[0x96, 0x01, 0x00, 0x01]);
+    // Then 50 of colour 1.
+    bytes.extend_from_slice(&[50, 0x01, 0x00]);
+
+    let sheet = decode(&bytes).unwrap();
+
+    assert_eq!(sheet.width, 20);
+    assert_eq!(sheet.height, 10);
+    assert_eq!(sheet.pixels.len(), 200);
+    assert!(sheet.pixels[..150].iter().all(|&p| p == 0xFF00_0100));
+    assert!(sheet.pixels[150..].iter().all(|&p| p == 0xFF00_0001));
+    assert!(sheet.palette_indices[..150].iter().all(|&i| i == 0));
+    assert!(sheet.palette_indices[150..].iter().all(|&i| i == 1));
+
+    assert_eq!(
+        decode(&bytes[..bytes.len() - 1]),
+        Err(DecodeError::UnexpectedEnd)
+    );
+
+    let last_run_length = bytes.len() - 3;
+    bytes[last_run_length] = 51;
+    assert_eq!(decode(&bytes), Err(DecodeError::TooManyPixels));
+}
+
+#[cfg(any(test, feature = "hot-reload"))]
+pub mod load {
+    use super::*;
+
+    use std::{
+        fs::File,
+        path::PathBuf,
+        time::SystemTime,
+    };
+
+    #[derive(Debug)]
+    pub enum Error {
+        Io(std::io::Error),
+        Decoding(png::DecodingError),
+        UnsupportedColourType(png::ColorType),
+    }
+
+    impl core::fmt::Display for Error {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            match self {
+                Error::Io(e) => write!(f, "{e}"),
+                Error::Decoding(e) => write!(f, "{e}"),
+                Error::UnsupportedColourType(colour_type) => write!(
+                    f,
+                    "{colour_type:?} images are not supported"
+                ),
+            }
+        }
+    }
+
+    impl std::error::Error for Error {}
+
+    impl From<std::io::Error> for Error {
+        fn from(e: std::io::Error) -> Self {
+            Error::Io(e)
+        }
+    }
+
+    impl From<png::DecodingError> for Error {
+        fn from(e: png::DecodingError) -> Self {
+            Error::Decoding(e)
+        }
+    }
+
+    /// The path to the given `Sheet::source`. This only works on the machine
+    /// this crate was built on, which is fine for reloading art during
+    /// development.
+    pub fn source_path(source: &str) -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "..", "..", "assets", source]
+            .iter()
+            .collect()
+    }
+
+    /// Reads a PNG file into a sheet, with the same pixel format that
+    /// `tooling/update_gfx` produces.
+    pub fn png_file(path: &std::path::Path) -> Result<OwnedSheet, Error> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        // Expand palettes and low bit depths to 8 bit RGB(A), so there are
+        // fewer cases to handle below.
+        decoder.set_transformations(
+            png::Transformations::EXPAND | png::Transformations::STRIP_16
+        );
+        let mut reader = decoder.read_info()?;
+
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        let bytes = &buf[..info.buffer_size()];
+
+        let argb = |a: u8, r: u8, g: u8, b: u8| -> ARGB {
+            (a as ARGB) << 24
+            | (r as ARGB) << 16
+            | (g as ARGB) << 8
+            | (b as ARGB)
+        };
+
+        use png::ColorType::*;
+        let pixels = match info.color_type {
+            Rgba => bytes.chunks_exact(4)
+                .map(|c| argb(c[3], c[0], c[1], c[2]))
+                .collect(),
+            Rgb => bytes.chunks_exact(3)
+                .map(|c| argb(255, c[0], c[1], c[2]))
+                .collect(),
+            GrayscaleAlpha => bytes.chunks_exact(2)
+                .map(|c| argb(c[1], c[0], c[0], c[0]))
+                .collect(),
+            Grayscale => bytes.iter()
+                .map(|&c| argb(255, c, c, c))
+                .collect(),
+            Indexed => return Err(Error::UnsupportedColourType(Indexed)),
+        };
+
+        Ok(OwnedSheet::from_pixels(
+            info.width as usize,
+            info.height as usize,
+            pixels,
+        ))
+    }
+
+    /// Reloads an image file whenever its modification time changes.
+    pub struct Watcher {
+        path: PathBuf,
+        modified: Option<SystemTime>,
+        missing: bool,
+    }
+
+    impl Watcher {
+        pub fn new(path: PathBuf) -> Self {
+            Self {
+                path,
+                modified: None,
+                missing: false,
+            }
+        }
+
+        pub fn path(&self) -> &std::path::Path {
+            &self.path
+        }
+
+        /// Returns `None` if the file has not changed since the last call,
+        /// and the result of loading it otherwise. The first call always
+        /// loads the file, if it exists. If the file can't be found, that
+        /// is only reported once, until it shows up again.
+        pub fn poll(&mut self) -> Option<Result<OwnedSheet, Error>> {
+            let modified = match std::fs::metadata(&self.path)
+                .and_then(|metadata| metadata.modified()) {
+                Ok(modified) => modified,
+                Err(_) if self.missing => return None,
+                Err(e) => {
+                    self.missing = true;
+                    return Some(Err(e.into()))
+                },
+            };
+            self.missing = false;
+
+            if self.modified == Some(modified) {
+                return None
+            }
+            self.modified = Some(modified);
+
+            Some(png_file(&self.path))
+        }
+    }
+
+    #[test]
+    fn loading_the_source_of_each_sheet_matches_the_decoded_pixels() {
+        use platform_types::{FONT_HEIGHT, FONT_LENGTH, FONT_WIDTH, GFX_HEIGHT, GFX_WIDTH};
+
+        let gfx = gfx();
+        assert_eq!(gfx.width, GFX_WIDTH);
+        assert_eq!(gfx.height, GFX_HEIGHT);
+
+        let font = font();
+        assert_eq!(font.width, FONT_WIDTH);
+        assert_eq!(font.height, FONT_HEIGHT);
+        assert_eq!(font.pixels.len(), FONT_LENGTH);
+
+        for id in SheetId::ALL {
+            let embedded = sheet(id);
+
+            let loaded = match png_file(&source_path(embedded.source)) {
+                Ok(loaded) => loaded,
+                Err(e) => panic!("{id:?}: {e}"),
+            };
+
+            assert_eq!(loaded.width, embedded.width, "{id:?}");
+            assert_eq!(loaded.height, embedded.height, "{id:?}");
+            assert!(loaded.pixels == embedded.pixels, "{id:?}");
+            assert!(
+                loaded.palette_indices == embedded.palette_indices,
+                "{id:?}"
+            );
+        }
+    }
+}
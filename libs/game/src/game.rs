@@ -1,144 +1,14 @@
 use models::{Card, gen_card};
-use platform_types::{sprite, unscaled};
+use platform_types::{sprite, sprites, unscaled};
 use xs::{Xs, Seed};
 
-pub const CUBE_W: unscaled::W = unscaled::W(111);
-pub const CUBE_H: unscaled::H = unscaled::H(128);
+pub const CUBE_W: unscaled::W = sprites::cube::WIDTH;
+pub const CUBE_H: unscaled::H = sprites::cube::HEIGHT;
 
-const CUBE_BASE_X: sprite::X = sprite::X(128);
-const CUBE_X_ADV: sprite::W = sprite::W(112);
+pub const CUBE_XYS: [sprite::XY; 6] = sprites::cube::FRAMES;
 
-const CUBE_BASE_Y: sprite::Y = sprite::Y(0);
-const CUBE_Y_ADV: sprite::H = sprite::H(128);
-
-macro_rules! compile_time_assert {
-    ($assertion: expr) => {{
-        #[allow(unknown_lints, eq_op)]
-        // Based on the const_assert macro from static_assertions;
-        const _: [(); 0 - !{$assertion} as usize] = [];
-    }}
-}
-
-pub const CUBE_XYS: [sprite::XY; 6] = {
-    use sprite::x_const_add_w as add_w;
-    use sprite::y_const_add_h as add_h;
-
-    compile_time_assert!{
-        sprite::x_const_add_w(CUBE_BASE_X, CUBE_X_ADV).0 < 32768
-    }
-
-    const X0: sprite::X = CUBE_BASE_X;
-    const X1: sprite::X = add_w(X0, CUBE_X_ADV);
-    const X2: sprite::X = add_w(X1, CUBE_X_ADV);
-
-    const Y0: sprite::Y = CUBE_BASE_Y;
-    const Y1: sprite::Y = add_h(Y0, CUBE_Y_ADV);
-
-    compile_time_assert!{
-        Y0.0 < 32768
-    }
-    compile_time_assert!{
-        Y1.0 < 32768
-    }
-
-    [
-        sprite::XY {
-            x: X0,
-            y: Y0,
-        },
-        sprite::XY {
-            x: X1,
-            y: Y0,
-        },
-        sprite::XY {
-            x: X2,
-            y: Y0,
-        },
-        sprite::XY {
-            x: X0,
-            y: Y1,
-        },
-        sprite::XY {
-            x: X1,
-            y: Y1,
-        },
-        sprite::XY {
-            x: X2,
-            y: Y1,
-        },
-    ]
-};
-
-const PLAYER_BASE_X: sprite::X = sprite::X(128);
-const PLAYER_X_ADV: sprite::W = sprite::W(112);
-
-const PLAYER_BASE_Y: sprite::Y = sprite::Y(256);
-const PLAYER_Y_ADV: sprite::H = sprite::H(128);
-
-pub const PLAYER_XYS: [sprite::XY; 12] = {
-    use sprite::x_const_add_w as add_w;
-    use sprite::y_const_add_h as add_h;
-
-    const X0: sprite::X = PLAYER_BASE_X;
-    const X1: sprite::X = add_w(X0, PLAYER_X_ADV);
-    const X2: sprite::X = add_w(X1, PLAYER_X_ADV);
-
-    const Y0: sprite::Y = PLAYER_BASE_Y;
-    const Y1: sprite::Y = add_h(Y0, PLAYER_Y_ADV);
-    const Y2: sprite::Y = add_h(Y1, PLAYER_Y_ADV);
-    const Y3: sprite::Y = add_h(Y2, PLAYER_Y_ADV);
-
-    [
-        sprite::XY {
-            x: X0,
-            y: Y0,
-        },
-        sprite::XY {
-            x: X1,
-            y: Y0,
-        },
-        sprite::XY {
-            x: X2,
-            y: Y0,
-        },
-        sprite::XY {
-            x: X0,
-            y: Y1,
-        },
-        sprite::XY {
-            x: X1,
-            y: Y1,
-        },
-        sprite::XY {
-            x: X2,
-            y: Y1,
-        },
-        sprite::XY {
-            x: X0,
-            y: Y2,
-        },
-        sprite::XY {
-            x: X1,
-            y: Y2,
-        },
-        sprite::XY {
-            x: X2,
-            y: Y2,
-        },
-        sprite::XY {
-            x: X0,
-            y: Y3,
-        },
-        sprite::XY {
-            x: X1,
-            y: Y3,
-        },
-        sprite::XY {
-            x: X2,
-            y: Y3,
-        },
-    ]
-};
+/// Indexed by `SubFace`.
+pub const PLAYER_XYS: [sprite::XY; 12] = sprites::player::FRAMES;
 
 // TODO tighter type?
 pub type CubeIndex = u8;
@@ -532,4 +402,15 @@ impl State {
     pub fn player_cell(&self) -> Cell {
         self.grid[grid_xy_to_i((self.player.x, self.player.y))]
     }
+
+    /// Whether the player is on one of the highest cells in the grid. Lower
+    /// `HZ`s are higher up.
+    pub fn is_at_summit(&self) -> bool {
+        let summit_hz = self.grid.iter()
+            .map(|cell| cell.hz)
+            .min()
+            .unwrap_or(HZ_BOTTOM);
+
+        self.player_cell().hz == summit_hz
+    }
 }
@@ -1,48 +1,228 @@
 use models::{Card, Rank, Suit, get_rank, get_suit, suits};
 
-use platform_types::{ARGB, Command, PALETTE, sprite, unscaled, command::{self, Rect}, PaletteIndex, FONT_BASE_Y, FONT_WIDTH};
+use platform_types::{ARGB, Command, PALETTE, sprite::{self, SheetId}, unscaled, command::{self, Alpha, Flip, Rect}, palette, PaletteIndex, sprites};
+
+/// Changes to how a sprite is drawn, beyond where it is drawn from and to.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Effects {
+    pub flip: Flip,
+    /// See `Command::tint`.
+    pub tint: ARGB,
+    pub alpha: Alpha,
+    /// See `Command::palette`.
+    pub palette: palette::Id,
+}
+
+/// A sprite that can be drawn at any size. The corners are drawn as they
+/// are, the edges are repeated along the sides, and the middle is repeated
+/// to fill the rest.
+#[derive(Clone, Copy, Debug)]
+pub struct NineSlice {
+    pub sheet: SheetId,
+    /// The top left corner of the whole sprite.
+    pub xy: sprite::XY,
+    pub w: unscaled::W,
+    pub h: unscaled::H,
+    /// The widths of the left and right edges, and the heights of the top
+    /// and bottom ones. The middle is whatever is left over.
+    pub left: unscaled::W,
+    pub right: unscaled::W,
+    pub top: unscaled::H,
+    pub bottom: unscaled::H,
+}
+
+/// Which group of things a command is drawn with. Later layers are drawn on
+/// top of earlier ones, regardless of the order the commands were added in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    Background,
+    #[default]
+    World,
+    Effects,
+    Ui,
+    Debug,
+    /// Covers everything else, for fading between scenes.
+    Transition,
+}
+
+/// Within a layer, commands with lower keys are drawn first. Commands with
+/// equal keys are drawn in the order they were added.
+pub type SortKey = i16;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DrawOrder {
+    pub layer: Layer,
+    pub key: SortKey,
+}
 
 #[derive(Default)]
 pub struct Commands {
+    unsorted: Vec<(DrawOrder, Command)>,
     commands: Vec<Command>,
+    order: DrawOrder,
+    needs_sort: bool,
+    logical_size: command::Size,
 }
 
 impl Commands {
-    pub fn slice(&self) -> &[Command] {
+    /// Returns the commands added since the last `clear`, stably sorted by
+    /// their `DrawOrder`.
+    pub fn slice(&mut self) -> &[Command] {
+        if self.needs_sort {
+            self.unsorted.sort_by_key(|&(order, _)| order);
+
+            self.commands.clear();
+            self.commands.extend(
+                self.unsorted.iter().map(|&(_, command)| command)
+            );
+
+            self.needs_sort = false;
+        }
+
         &self.commands
     }
 
     pub fn clear(&mut self) {
+        self.unsorted.clear();
         self.commands.clear();
+        self.order = DrawOrder::default();
+        self.needs_sort = false;
+    }
+
+    pub fn draw_order(&self) -> DrawOrder {
+        self.order
+    }
+
+    /// Sets the `DrawOrder` used for all commands added after this call.
+    pub fn set_draw_order(&mut self, order: DrawOrder) {
+        self.order = order;
+    }
+
+    pub fn set_layer(&mut self, layer: Layer) {
+        self.order.layer = layer;
+    }
+
+    pub fn set_sort_key(&mut self, key: SortKey) {
+        self.order.key = key;
+    }
+
+    /// Sets the size of the frame that commands added after this call are
+    /// clipped to.
+    pub fn set_logical_size(&mut self, logical_size: command::Size) {
+        self.logical_size = logical_size;
+    }
+
+    fn push(&mut self, command: Command) {
+        self.unsorted.push((self.order, command));
+        self.needs_sort = true;
     }
 
     pub fn sspr(
         &mut self,
+        sheet: SheetId,
         sprite_xy: sprite::XY,
         rect: unscaled::Rect,
     ) {
-        if let Ok(cmd) = Command::clipped(
+        self.sspr_flipped(sheet, sprite_xy, rect, Flip::NONE);
+    }
+
+    pub fn sspr_flipped(
+        &mut self,
+        sheet: SheetId,
+        sprite_xy: sprite::XY,
+        rect: unscaled::Rect,
+        flip: Flip,
+    ) {
+        self.sspr_with(
+            sheet,
+            sprite_xy,
+            rect,
+            Effects {
+                flip,
+                ..<_>::default()
+            },
+        );
+    }
+
+    pub fn sspr_with(
+        &mut self,
+        sheet: SheetId,
+        sprite_xy: sprite::XY,
+        rect: unscaled::Rect,
+        Effects { flip, tint, alpha, palette }: Effects,
+    ) {
+        if alpha == Alpha::TRANSPARENT {
+            return
+        }
+
+        if let Ok(mut cmd) = Command::clipped_to(
+            self.logical_size,
             rect,
             sprite_xy,
             0,
+            flip,
         ) {
-            self.commands.push(cmd);
+            cmd.sheet = sheet;
+            cmd.tint = tint;
+            cmd.alpha = alpha;
+            cmd.palette = palette;
+            self.push(cmd);
         }
     }
 
+    /// Prints `line` on one line, with each character taking up as much space
+    /// as `text::advance` says. Characters the font has no glyph for are
+    /// drawn as `text::REPLACEMENT`. See `print_in` for wrapping.
     pub fn print_line(
         &mut self,
-        line: &[u8],
+        line: &str,
         x: unscaled::X,
         y: unscaled::Y,
         colour: PaletteIndex
     ) {
         let mut x = x;
-        for &byte in line {
-            type Inner = sprite::Inner;
-            self.print_char(byte, x, y, colour);
-            x += CHAR_W;
+        for character in line.chars() {
+            let glyph = text::glyph(character);
+            self.print_char(glyph.index, x, y, colour);
+            if let Some(mark) = glyph.mark {
+                self.print_char(mark.index(), x, y + mark.dy(glyph.index), colour);
+            }
+            x += text::glyph_advance(glyph.index);
+        }
+    }
+
+    /// Prints `text` inside `rect`, wrapped to its width. Lines that would
+    /// go past the bottom of `rect` are not drawn. Returns how much of the
+    /// height of `rect` the drawn lines take up.
+    pub fn print_in(
+        &mut self,
+        text: &str,
+        rect: unscaled::Rect,
+        style: text::Style,
+    ) -> unscaled::H {
+        let bottom = rect.y + rect.h;
+        let mut y = rect.y;
+
+        for (i, line) in text::wrap(text, rect.w).into_iter().enumerate() {
+            if i > 0 {
+                y += style.line_spacing;
+            }
+
+            if y + CHAR_H > bottom {
+                // Don't count the spacing above a line that isn't drawn.
+                if i > 0 {
+                    y -= style.line_spacing;
+                }
+                break
+            }
+
+            let x = rect.x + style.align.offset(text::line_width(line), rect.w);
+            self.print_line(line, x, y, style.colour);
+
+            y += CHAR_H;
         }
+
+        y - rect.y
     }
 
     pub fn print_char(
@@ -52,26 +232,21 @@ impl Commands {
         y: unscaled::Y,
         colour: PaletteIndex
     ) {
-        fn get_char_xy(sprite_number: u8) -> sprite::XY {
-            type Inner = sprite::Inner;
-            let sprite_number = Inner::from(sprite_number);
-            const CH_SIZE: Inner = CHAR_SIZE as Inner;
-            const SPRITES_PER_ROW: Inner = FONT_WIDTH as Inner / CH_SIZE;
-
-            sprite::XY {
-                x: sprite::X(
-                    (sprite_number % SPRITES_PER_ROW) * CH_SIZE
-                ),
-                y: sprite::Y(
-                    FONT_BASE_Y as Inner +
-                    (sprite_number / SPRITES_PER_ROW) * CH_SIZE
-                ),
-            }
-        }
+        self.print_char_flipped(character, x, y, colour, Flip::NONE);
+    }
 
-        let sprite_xy = get_char_xy(character);
+    pub fn print_char_flipped(
+        &mut self,
+        character: u8,
+        x: unscaled::X,
+        y: unscaled::Y,
+        colour: PaletteIndex,
+        flip: Flip,
+    ) {
+        let sprite_xy = sprites::font::FRAMES[usize::from(character)];
 
-        if let Ok(cmd) = Command::clipped(
+        if let Ok(mut cmd) = Command::clipped_to(
+            self.logical_size,
             unscaled::Rect {
                 x,
                 y,
@@ -80,8 +255,119 @@ impl Commands {
             },
             sprite_xy,
             PALETTE[colour as usize],
+            flip,
         ) {
-            self.commands.push(cmd);
+            cmd.sheet = sprites::font::SHEET;
+            self.push(cmd);
+        }
+    }
+
+    /// Fills `rect` with `colour`.
+    pub fn fill_rect(&mut self, rect: unscaled::Rect, colour: PaletteIndex) {
+        self.fill_rect_with(rect, colour, Alpha::OPAQUE);
+    }
+
+    /// Fills `rect` with `colour`, blended with what is under it by `alpha`.
+    pub fn fill_rect_with(
+        &mut self,
+        rect: unscaled::Rect,
+        colour: PaletteIndex,
+        alpha: Alpha,
+    ) {
+        if alpha == Alpha::TRANSPARENT {
+            return
+        }
+
+        if let Ok(mut cmd) = Command::filled_to(
+            self.logical_size,
+            rect,
+            PALETTE[colour as usize],
+        ) {
+            cmd.alpha = alpha;
+            self.push(cmd);
+        }
+    }
+
+    /// Draws a one pixel wide border just inside the edges of `rect`.
+    pub fn outline_rect(&mut self, rect: unscaled::Rect, colour: PaletteIndex) {
+        use unscaled::{W, H};
+
+        if rect.w <= W(0) || rect.h <= H(0) {
+            return
+        }
+
+        let unscaled::Rect { x, y, w, h } = rect;
+
+        self.fill_rect(unscaled::Rect { x, y, w, h: H(1) }, colour);
+        if h > H(1) {
+            self.fill_rect(
+                unscaled::Rect { x, y: y + h - H(1), w, h: H(1) },
+                colour
+            );
+        }
+
+        let side_h = h - H(2);
+        if side_h > H(0) {
+            self.fill_rect(
+                unscaled::Rect { x, y: y + H(1), w: W(1), h: side_h },
+                colour
+            );
+            if w > W(1) {
+                self.fill_rect(
+                    unscaled::Rect { x: x + w - W(1), y: y + H(1), w: W(1), h: side_h },
+                    colour
+                );
+            }
+        }
+    }
+
+    /// Draws a one pixel wide line from `from` to `to`, including both ends.
+    pub fn line(
+        &mut self,
+        from: unscaled::XY,
+        to: unscaled::XY,
+        colour: PaletteIndex
+    ) {
+        line_rects(from, to, |rect| self.fill_rect(rect, colour));
+    }
+
+    /// Draws `panel` so it covers `rect`. If `rect` is smaller than the
+    /// corners of `panel`, they overlap.
+    pub fn nine_slice(&mut self, panel: &NineSlice, rect: unscaled::Rect) {
+        use unscaled::{W, H};
+
+        let middle_w = panel.w - panel.left - panel.right;
+        let middle_h = panel.h - panel.top - panel.bottom;
+
+        // (source offset, source length, destination offset, destination length)
+        let columns = [
+            (W(0), panel.left, W(0), panel.left),
+            (panel.left, middle_w, panel.left, rect.w - panel.left - panel.right),
+            (panel.w - panel.right, panel.right, rect.w - panel.right, panel.right),
+        ];
+        let rows = [
+            (H(0), panel.top, H(0), panel.top),
+            (panel.top, middle_h, panel.top, rect.h - panel.top - panel.bottom),
+            (panel.h - panel.bottom, panel.bottom, rect.h - panel.bottom, panel.bottom),
+        ];
+
+        for (src_y, src_h, dest_y, dest_h) in rows {
+            for (src_x, src_w, dest_x, dest_w) in columns {
+                for (x_offset, w) in tile_spans(dest_w.0, src_w.0) {
+                    for (y_offset, h) in tile_spans(dest_h.0, src_h.0) {
+                        self.sspr(
+                            panel.sheet,
+                            panel.xy + src_x + src_y,
+                            unscaled::Rect {
+                                x: rect.x + dest_x + W(x_offset),
+                                y: rect.y + dest_y + H(y_offset),
+                                w: W(w),
+                                h: H(h),
+                            }
+                        );
+                    }
+                }
+            }
         }
     }
 
@@ -92,10 +378,8 @@ impl Commands {
         y: unscaled::Y
     ) {
         self.sspr(
-            sprite::XY {
-                x: sprite::X(card::FRONT_SPRITE_X as _),
-                y: sprite::Y(card::FRONT_SPRITE_Y as _),
-            },
+            sprites::card_front::SHEET,
+            sprites::card_front::XY,
             unscaled::Rect {
                 x,
                 y,
@@ -121,31 +405,172 @@ impl Commands {
             colour,
         );
 
-        self.print_char(
-            rank_char | FONT_FLIP,
+        self.print_char_flipped(
+            rank_char,
             x + card::RIGHT_RANK_EDGE_W,
             y + card::RIGHT_RANK_EDGE_H,
             colour,
+            Flip::BOTH,
         );
-        self.print_char(
-            suit_char | FONT_FLIP,
+        self.print_char_flipped(
+            suit_char,
             x + card::RIGHT_SUIT_EDGE_W,
             y + card::RIGHT_SUIT_EDGE_H,
             colour,
+            Flip::BOTH,
         );
     }
 }
 
+/// Splits a span `len` long into pieces at most `piece_len` long, returned as
+/// their offsets and lengths.
+fn tile_spans(
+    len: unscaled::LengthInner,
+    piece_len: unscaled::LengthInner,
+) -> impl Iterator<Item = (unscaled::LengthInner, unscaled::LengthInner)> {
+    let count = if len > 0 && piece_len > 0 {
+        (len + piece_len - 1) / piece_len
+    } else {
+        0
+    };
+
+    (0..count).map(move |i| {
+        let offset = i * piece_len;
+        (offset, core::cmp::min(piece_len, len - offset))
+    })
+}
+
+/// Calls `f` with rects that cover the pixels of a line from `from` to `to`.
+/// Consecutive pixels in the same row, or column for steep lines, share a
+/// rect.
+fn line_rects(
+    from: unscaled::XY,
+    to: unscaled::XY,
+    mut f: impl FnMut(unscaled::Rect),
+) {
+    let (x0, y0) = (i32::from(from.x.0), i32::from(from.y.0));
+    let (x1, y1) = (i32::from(to.x.0), i32::from(to.y.0));
+
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let sx = if x0 < x1 { 1 } else { -1 };
+    let sy = if y0 < y1 { 1 } else { -1 };
+    let shallow = dx >= -dy;
+
+    let mut emit = |(ax, ay): (i32, i32), (bx, by): (i32, i32)| {
+        let inner = |n: i32| n as unscaled::Inner;
+        f(unscaled::Rect {
+            x: unscaled::X(inner(ax.min(bx))),
+            y: unscaled::Y(inner(ay.min(by))),
+            w: unscaled::W(inner((bx - ax).abs() + 1)),
+            h: unscaled::H(inner((by - ay).abs() + 1)),
+        });
+    };
+
+    // Bresenham's line algorithm.
+    let mut err = dx + dy;
+    let (mut x, mut y) = (x0, y0);
+    let mut run_start = (x, y);
+    while (x, y) != (x1, y1) {
+        let (mut next_x, mut next_y) = (x, y);
+        let doubled = 2 * err;
+        if doubled >= dy {
+            err += dy;
+            next_x += sx;
+        }
+        if doubled <= dx {
+            err += dx;
+            next_y += sy;
+        }
+
+        if (shallow && next_y != y) || (!shallow && next_x != x) {
+            emit(run_start, (x, y));
+            run_start = (next_x, next_y);
+        }
+
+        (x, y) = (next_x, next_y);
+    }
+    emit(run_start, (x, y));
+}
+
+#[test]
+fn line_rects_covers_each_pixel_of_the_line_once() {
+    let xy = |x, y| unscaled::XY { x: unscaled::X(x), y: unscaled::Y(y) };
+
+    let collect = |from, to| {
+        let mut rects = Vec::new();
+        line_rects(from, to, |rect| rects.push(rect));
+        rects
+    };
+
+    let horizontal = collect(xy(5, 2), xy(1, 2));
+    assert_eq!(horizontal.len(), 1);
+    assert_eq!(horizontal[0], unscaled::Rect {
+        x: unscaled::X(1),
+        y: unscaled::Y(2),
+        w: unscaled::W(5),
+        h: unscaled::H(1),
+    });
+
+    let diagonal = collect(xy(0, 0), xy(3, 3));
+    assert_eq!(diagonal.len(), 4);
+    assert!(diagonal.iter().all(|rect| rect.w.0 == 1 && rect.h.0 == 1));
+
+    for (to_x, to_y) in [(10, 3), (-4, 9), (7, -7), (0, 0)] {
+        let rects = collect(xy(0, 0), xy(to_x, to_y));
+        let pixels: i16 = rects.iter().map(|rect| rect.w.0 * rect.h.0).sum();
+        assert_eq!(pixels, core::cmp::max(to_x.abs(), to_y.abs()) + 1);
+    }
+}
+
+#[test]
+fn commands_are_clipped_to_the_logical_size() {
+    let mut commands = Commands::default();
+    commands.set_logical_size(command::Size::new(320, 180).unwrap());
+
+    commands.fill_rect(
+        unscaled::Rect {
+            x: unscaled::X(300),
+            y: unscaled::Y(170),
+            w: unscaled::W(100),
+            h: unscaled::H(100),
+        },
+        0,
+    );
+    commands.fill_rect(
+        unscaled::Rect {
+            x: unscaled::X(-20),
+            y: unscaled::Y(-20),
+            w: unscaled::W(10),
+            h: unscaled::H(10),
+        },
+        0,
+    );
+
+    let slice = commands.slice();
+    assert_eq!(slice.len(), 1);
+    assert_eq!(slice[0].rect.x_max.get(), unscaled::X(319));
+    assert_eq!(slice[0].rect.y_max.get(), unscaled::Y(179));
+}
+
+#[test]
+fn tile_spans_covers_the_length_with_whole_pieces_then_a_partial_one() {
+    let spans = |len, piece_len| tile_spans(len, piece_len).collect::<Vec<_>>();
+
+    assert_eq!(spans(10, 4), [(0, 4), (4, 4), (8, 2)]);
+    assert_eq!(spans(8, 4), [(0, 4), (4, 4)]);
+    assert_eq!(spans(0, 4), []);
+    assert_eq!(spans(-3, 4), []);
+    assert_eq!(spans(10, 0), []);
+}
+
 pub mod card {
     use super::*;
 
     use unscaled::{W, H, w_const_add, w_const_sub, h_const_add, h_const_sub};
 
-    pub const WIDTH: W = W(20);
-    pub const HEIGHT: H = H(30);
-
-    pub const FRONT_SPRITE_X: u8 = 2;
-    pub const FRONT_SPRITE_Y: u8 = 1;
+    pub const WIDTH: W = sprites::card_front::WIDTH;
+    pub const HEIGHT: H = sprites::card_front::HEIGHT;
 
     pub const LEFT_RANK_EDGE_W: W = W(3);
     pub const LEFT_RANK_EDGE_H: H = H(3);
@@ -172,6 +597,376 @@ pub mod card {
     );
 }
 
+/// Turning text into glyphs from the font, and laying out lines of them,
+/// where each glyph is only as wide as the visible part of its sprite.
+pub mod text {
+    use super::*;
+
+    use unscaled::{W, H, w_const_add, w_const_div};
+
+    /// An accent, drawn over or under another glyph. Each one has its own
+    /// glyph in the font, with the mark in the top two rows.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Mark {
+        Acute,
+        Grave,
+        Circumflex,
+        Tilde,
+        Diaeresis,
+        Ring,
+        Cedilla,
+    }
+
+    impl Mark {
+        pub const fn index(self) -> u8 {
+            match self {
+                Mark::Acute => 2,
+                Mark::Grave => 3,
+                Mark::Circumflex => 4,
+                Mark::Tilde => 5,
+                Mark::Diaeresis => 6,
+                Mark::Ring => 7,
+                Mark::Cedilla => 8,
+            }
+        }
+
+        /// How far down from the top of the glyph at `base` to draw the mark.
+        pub fn dy(self, base: u8) -> H {
+            match self {
+                // Under the bottom row, which is the same for every letter.
+                Mark::Cedilla => H(5),
+                // Leave a row between the mark and the top of the letter.
+                // The font's uppercase letters are a row shorter than its
+                // lowercase ones.
+                _ if base.is_ascii_uppercase() => H(-2),
+                _ => H(-3),
+            }
+        }
+    }
+
+    /// Which glyph in the font to draw for a character, along with the mark,
+    /// if any, to draw with it.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Glyph {
+        pub index: u8,
+        pub mark: Option<Mark>,
+    }
+
+    /// Drawn for characters the font has no glyph for.
+    pub const REPLACEMENT: u8 = 9;
+
+    /// The glyph to draw for `character`. Printable ASCII characters use the
+    /// glyph with the same index. Accented Latin letters are drawn as the
+    /// unaccented letter with a `Mark`. Characters without a glyph get
+    /// `REPLACEMENT`.
+    pub fn glyph(character: char) -> Glyph {
+        use Mark::*;
+
+        let plain = |index| Glyph { index, mark: None };
+        let marked = |base: char, mark| Glyph {
+            index: base as u8,
+            mark: Some(mark),
+        };
+
+        match character {
+            ' '..='~' => plain(character as u8),
+            '\u{A0}' => plain(b' '),
+            '‘' | '’' => plain(b'\''),
+            '“' | '”' => plain(b'"'),
+            '–' | '—' => plain(b'-'),
+
+            '°' => plain(11),
+            '•' | '·' => plain(12),
+            '×' => plain(13),
+            '÷' => plain(14),
+            '←' => plain(15),
+            '→' => plain(16),
+            '↑' => plain(17),
+            '↓' => plain(18),
+            '…' => plain(19),
+            '£' => plain(20),
+            '€' => plain(21),
+            '¿' => plain(22),
+            '¡' => plain(23),
+            '«' => plain(24),
+            '»' => plain(25),
+            '♠' => plain(SPADE_CHAR),
+            '♦' => plain(DIAMOND_CHAR),
+            '♥' => plain(HEART_CHAR),
+            '♣' => plain(CLUB_CHAR),
+
+            'À' => marked('A', Grave),
+            'Á' => marked('A', Acute),
+            'Â' => marked('A', Circumflex),
+            'Ã' => marked('A', Tilde),
+            'Ä' => marked('A', Diaeresis),
+            'Å' => marked('A', Ring),
+            'Ç' => marked('C', Cedilla),
+            'È' => marked('E', Grave),
+            'É' => marked('E', Acute),
+            'Ê' => marked('E', Circumflex),
+            'Ë' => marked('E', Diaeresis),
+            'Ì' => marked('I', Grave),
+            'Í' => marked('I', Acute),
+            'Î' => marked('I', Circumflex),
+            'Ï' => marked('I', Diaeresis),
+            'Ñ' => marked('N', Tilde),
+            'Ò' => marked('O', Grave),
+            'Ó' => marked('O', Acute),
+            'Ô' => marked('O', Circumflex),
+            'Õ' => marked('O', Tilde),
+            'Ö' => marked('O', Diaeresis),
+            'Ù' => marked('U', Grave),
+            'Ú' => marked('U', Acute),
+            'Û' => marked('U', Circumflex),
+            'Ü' => marked('U', Diaeresis),
+            'Ý' => marked('Y', Acute),
+            'à' => marked('a', Grave),
+            'á' => marked('a', Acute),
+            'â' => marked('a', Circumflex),
+            'ã' => marked('a', Tilde),
+            'ä' => marked('a', Diaeresis),
+            'å' => marked('a', Ring),
+            'ç' => marked('c', Cedilla),
+            'è' => marked('e', Grave),
+            'é' => marked('e', Acute),
+            'ê' => marked('e', Circumflex),
+            'ë' => marked('e', Diaeresis),
+            'ì' => marked('i', Grave),
+            'í' => marked('i', Acute),
+            'î' => marked('i', Circumflex),
+            'ï' => marked('i', Diaeresis),
+            'ñ' => marked('n', Tilde),
+            'ò' => marked('o', Grave),
+            'ó' => marked('o', Acute),
+            'ô' => marked('o', Circumflex),
+            'õ' => marked('o', Tilde),
+            'ö' => marked('o', Diaeresis),
+            'ù' => marked('u', Grave),
+            'ú' => marked('u', Acute),
+            'û' => marked('u', Circumflex),
+            'ü' => marked('u', Diaeresis),
+            'ý' => marked('y', Acute),
+            'ÿ' => marked('y', Diaeresis),
+
+            _ => plain(REPLACEMENT),
+        }
+    }
+
+    /// The gap left between each glyph and the next one.
+    pub const GLYPH_SPACING: W = W(1);
+
+    /// How far glyphs with no visible pixels, like spaces, move the next
+    /// glyph along.
+    pub const EMPTY_ADVANCE: W = w_const_add(W(3), GLYPH_SPACING);
+
+    /// How far along the glyph after the one at `index` should be drawn.
+    pub fn glyph_advance(index: u8) -> W {
+        match sprites::font::WIDTHS[usize::from(index)] {
+            0 => EMPTY_ADVANCE,
+            width => W(unscaled::inner_from_u8(width)) + GLYPH_SPACING,
+        }
+    }
+
+    /// How far along the character after `character` should be drawn.
+    /// Marks do not affect this.
+    pub fn advance(character: char) -> W {
+        glyph_advance(glyph(character).index)
+    }
+
+    /// The width of `line` when printed, not counting the gap after the last
+    /// glyph.
+    pub fn line_width(line: &str) -> W {
+        if line.is_empty() {
+            return W(0)
+        }
+
+        let mut width = W(0);
+        for character in line.chars() {
+            width += advance(character);
+        }
+        width - GLYPH_SPACING
+    }
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum Align {
+        #[default]
+        Left,
+        Center,
+        Right,
+    }
+
+    impl Align {
+        /// How far from the left of a space `space_w` wide a line
+        /// `line_w` wide should start.
+        pub fn offset(self, line_w: W, space_w: W) -> W {
+            let spare = core::cmp::max(W(0), space_w - line_w);
+            match self {
+                Align::Left => W(0),
+                Align::Center => w_const_div(spare, 2),
+                Align::Right => spare,
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Style {
+        pub colour: PaletteIndex,
+        pub align: Align,
+        /// Extra space between each line and the next one, on top of the
+        /// height of the glyphs.
+        pub line_spacing: H,
+    }
+
+    /// Splits `text` into lines that are each at most `max_w` wide, when
+    /// printed. Lines are broken at spaces where possible, and a `\n`
+    /// always starts a new line. Words too long to fit on a line by
+    /// themselves are split wherever they need to be. The spaces a line is
+    /// broken at are left out.
+    pub fn wrap(text: &str, max_w: W) -> Vec<&str> {
+        let mut lines = Vec::new();
+
+        for paragraph in text.split('\n') {
+            let mut rest = paragraph;
+            loop {
+                // The total advance of the characters before `end`.
+                let mut advanced = W(0);
+                let mut end = rest.len();
+                let mut last_space = None;
+                for (i, character) in rest.char_indices() {
+                    let next = advanced + advance(character);
+                    // The first character always goes on the line, so we
+                    // always make progress, even if `max_w` is tiny.
+                    if i > 0 && next - GLYPH_SPACING > max_w {
+                        end = i;
+                        break
+                    }
+                    if character == ' ' {
+                        last_space = Some(i);
+                    }
+                    advanced = next;
+                }
+
+                if end == rest.len() {
+                    lines.push(rest);
+                    break
+                }
+
+                let line_end = match last_space {
+                    _ if rest[end..].starts_with(' ') => end,
+                    Some(space) if space > 0 => space,
+                    _ => end,
+                };
+
+                lines.push(rest[..line_end].trim_end_matches(' '));
+
+                rest = rest[line_end..].trim_start_matches(' ');
+                if rest.is_empty() {
+                    break
+                }
+            }
+        }
+
+        lines
+    }
+
+    /// The size of `text` once wrapped to `max_w`, with `line_spacing`
+    /// between each line.
+    pub fn measure(text: &str, max_w: W, line_spacing: H) -> (W, H) {
+        let lines = wrap(text, max_w);
+
+        let mut w = W(0);
+        let mut h = H(0);
+        for (i, line) in lines.iter().enumerate() {
+            w = core::cmp::max(w, line_width(line));
+            if i > 0 {
+                h += line_spacing;
+            }
+            h += CHAR_H;
+        }
+
+        (w, h)
+    }
+
+    #[test]
+    fn glyph_maps_ascii_directly_and_unknown_characters_to_the_replacement() {
+        for byte in b' '..=b'~' {
+            assert_eq!(glyph(char::from(byte)), Glyph { index: byte, mark: None });
+        }
+
+        assert_eq!(glyph('é'), Glyph { index: b'e', mark: Some(Mark::Acute) });
+        assert_eq!(glyph('\t'), Glyph { index: REPLACEMENT, mark: None });
+        assert_eq!(glyph('字'), Glyph { index: REPLACEMENT, mark: None });
+    }
+
+    #[test]
+    fn every_glyph_the_table_uses_is_visible() {
+        let used = ('\0'..='\u{FFFF}')
+            .map(glyph)
+            .filter(|glyph| glyph.index != b' ');
+
+        for Glyph { index, mark } in used {
+            assert_ne!(sprites::font::WIDTHS[usize::from(index)], 0, "{index}");
+            if let Some(mark) = mark {
+                assert_ne!(sprites::font::WIDTHS[usize::from(mark.index())], 0, "{mark:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn line_width_adds_up_advances_without_the_last_gap() {
+        assert_eq!(line_width(""), W(0));
+        assert_eq!(line_width("a"), advance('a') - GLYPH_SPACING);
+        assert_eq!(
+            line_width("a b"),
+            advance('a') + EMPTY_ADVANCE + advance('b') - GLYPH_SPACING
+        );
+        assert_eq!(line_width("é"), line_width("e"));
+    }
+
+    #[test]
+    fn wrap_breaks_at_spaces_and_newlines() {
+        let word_w = line_width("abc");
+
+        assert_eq!(wrap("abc abc abc", word_w), ["abc"; 3]);
+        assert_eq!(
+            wrap("abc abc abc", line_width("abc abc")),
+            ["abc abc", "abc"]
+        );
+        assert_eq!(wrap("abc\n\nabc", W(1000)), ["abc", "", "abc"]);
+    }
+
+    #[test]
+    fn wrap_splits_words_that_do_not_fit_on_a_line() {
+        let w = line_width("ab");
+
+        assert_eq!(wrap("abcde", w), ["ab", "cd", "e"]);
+        // Even when nothing fits, each line gets one character.
+        assert_eq!(wrap("ab", W(0)), ["a", "b"]);
+        // Multi-byte characters are not split.
+        assert_eq!(wrap("éé", W(0)), ["é", "é"]);
+    }
+
+    #[test]
+    fn measure_counts_lines_and_spacing() {
+        let word_w = line_width("abc");
+
+        assert_eq!(
+            measure("abc abc abc", word_w, H(2)),
+            (word_w, CHAR_H + H(2) + CHAR_H + H(2) + CHAR_H)
+        );
+        assert_eq!(measure("", word_w, H(2)), (W(0), CHAR_H));
+    }
+
+    #[test]
+    fn align_offsets_lines_within_the_space() {
+        assert_eq!(Align::Left.offset(W(4), W(10)), W(0));
+        assert_eq!(Align::Center.offset(W(4), W(10)), W(3));
+        assert_eq!(Align::Right.offset(W(4), W(10)), W(6));
+        assert_eq!(Align::Right.offset(W(12), W(10)), W(0));
+    }
+}
+
 pub const TEN_CHAR: u8 = 27;
 
 pub const CLUB_CHAR: u8 = 31;
@@ -216,9 +1011,53 @@ pub fn get_rank_char_from_rank(rank: Rank) -> u8 {
     }
 }
 
-pub const CHAR_SIZE: u8 = 8;
-pub const CHAR_W: unscaled::W = unscaled::W(CHAR_SIZE as _);
-pub const CHAR_H: unscaled::H = unscaled::H(CHAR_SIZE as _);
+pub const CHAR_W: unscaled::W = sprites::font::WIDTH;
+pub const CHAR_H: unscaled::H = sprites::font::HEIGHT;
 
+/// Selects the pre-flipped half of the font. Prefer passing `Flip::BOTH` to
+/// `print_char_flipped`, which does not rely on the font containing a flipped
+/// copy of each glyph.
 pub const FONT_FLIP: u8 = 128;
 
+#[test]
+fn slice_is_sorted_by_draw_order_then_insertion_order() {
+    let mut commands = Commands::default();
+
+    let rect = unscaled::Rect {
+        x: unscaled::X(0),
+        y: unscaled::Y(0),
+        w: unscaled::W(1),
+        h: unscaled::H(1),
+    };
+
+    macro_rules! add {
+        ($layer: ident, $key: literal, $sprite_x: literal) => {
+            commands.set_draw_order(DrawOrder {
+                layer: Layer::$layer,
+                key: $key,
+            });
+            commands.sspr(
+                SheetId::Tiles,
+                sprite::XY {
+                    x: sprite::X($sprite_x),
+                    y: sprite::Y(0),
+                },
+                rect,
+            );
+        }
+    }
+
+    add!(Debug, 0, 0);
+    add!(World, 1, 1);
+    add!(World, 0, 2);
+    add!(Ui, 0, 3);
+    add!(World, 1, 4);
+    add!(Background, 9, 5);
+
+    let actual = commands.slice()
+        .iter()
+        .map(|command| command.sprite_xy.x.0)
+        .collect::<Vec<_>>();
+
+    assert_eq!(actual, [5, 2, 1, 4, 3, 0]);
+}
@@ -1,30 +1,45 @@
 use platform_types::{
     State,
     StateParams,
+    timestep,
 };
 
-use softbuffer::GraphicsContext;
+use softbuffer::{Context, Surface};
 
 use winit::{
     event::{Event, WindowEvent},
     event_loop::{EventLoop, ControlFlow},
-    window::WindowBuilder,
+    keyboard::ModifiersState,
+    window::{Fullscreen, Window, WindowBuilder},
 };
 
 use render::{clip, FrameBuffer, NeedsRedraw};
 
+use std::{num::NonZeroU32, rc::Rc};
+
 pub fn run<S: State + 'static>(mut state: S) {
-    let event_loop = EventLoop::new();
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let logical_size = state.logical_size();
 
     let builder = WindowBuilder::new()
         .with_title("peak");
 
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut config = config::Config::load();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let builder = config.apply_to(builder);
+
     #[cfg(target_arch = "wasm32")]
-    let builder = wasm::set_canvas(builder);
+    let builder = wasm::set_canvas(builder, logical_size);
 
-    let window = builder
-        .build(&event_loop)
-        .unwrap();
+    let window = Rc::new(
+        builder
+            .build(&event_loop)
+            .unwrap()
+    );
 
     // This must happen after the `build` call, or the style gets overridden.
     #[cfg(target_arch = "wasm32")]
@@ -33,55 +48,159 @@ pub fn run<S: State + 'static>(mut state: S) {
     let mut output_frame_buffer = {
         let size = window.inner_size();
 
-        FrameBuffer::from_size((size.width as clip::W, size.height as clip::H))
+        FrameBuffer::new(
+            (size.width as clip::W, size.height as clip::H),
+            logical_size,
+        )
     };
 
-    let mut graphics_context = unsafe { GraphicsContext::new(window) }.unwrap();
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        output_frame_buffer.scale_mode = config.scale_mode;
+    }
+
+    let context = Context::new(Rc::clone(&window)).unwrap();
+    let mut surface = Surface::new(&context, Rc::clone(&window)).unwrap();
+    let mut surface_size = (0, 0);
 
     let mut sound_handler = init_sound_handler();
 
+    // This only limits how often frames are drawn. `accumulator` decides
+    // how often the game is updated, the same way on every platform.
     #[cfg(not(target_arch = "wasm32"))]
     let mut loop_helper = spin_sleep::LoopHelper::builder()
             .build_with_target_rate(60.0);
 
+    let mut accumulator = timestep::Accumulator::default();
+    let clock = Clock::new();
+
     let mut just_gained_focus = true;
 
-    event_loop.run(move |event, _, control_flow| {
-        let window = graphics_context.window();
+    let mut suspended = false;
+
+    let mut modifiers = ModifiersState::default();
 
+    #[cfg(target_arch = "wasm32")]
+    let visibility = wasm::Visibility::listen();
+
+    #[cfg(all(
+        not(target_arch = "wasm32"),
+        feature = "hot-reload",
+        debug_assertions,
+    ))]
+    let mut sheet_reloader = hot_reload::SheetReloader::new();
+
+    state.init();
+
+    event_loop.run(move |event, target| {
         match event {
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 window_id,
-            } if window_id == window.id() => *control_flow = ControlFlow::Exit,
+            } if window_id == window.id()
+            && state.quit_requested() => target.exit(),
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                window_id,
+            } if window_id == window.id() => {
+                #[cfg(not(target_arch = "wasm32"))]
+                config.window_resized(&window, size);
+
+                state.resized(size.width, size.height);
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Event::WindowEvent {
+                event: WindowEvent::Moved(position),
+                window_id,
+            } if window_id == window.id() => {
+                config.window_moved(&window, position);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::ModifiersChanged(new_modifiers),
+                window_id,
+            } if window_id == window.id() => {
+                modifiers = new_modifiers.state();
+            }
+            Event::Suspended => set_suspended(
+                &mut state,
+                &mut suspended,
+                &mut accumulator,
+                true,
+            ),
+            Event::Resumed => set_suspended(
+                &mut state,
+                &mut suspended,
+                &mut accumulator,
+                false,
+            ),
             Event::WindowEvent {
                 event: WindowEvent::KeyboardInput{
-                    input: winit::event::KeyboardInput {
+                    event: winit::event::KeyEvent {
                         state: element_state,
-                        virtual_keycode: Some(keycode),
+                        physical_key,
+                        text,
                         ..
                     },
                     ..
                 },
                 window_id,
             } if window_id == window.id() => {
-                use winit::event::{ElementState, VirtualKeyCode as VK};
+                use winit::{
+                    event::ElementState,
+                    keyboard::{KeyCode as K, PhysicalKey},
+                };
                 use platform_types::Button;
 
-                let button = match keycode {
-                    VK::Return => Button::START,
-                    VK::RShift => Button::SELECT,
-                    VK::Up => Button::UP,
-                    VK::Left => Button::LEFT,
-                    VK::Right => Button::RIGHT,
-                    VK::Down => Button::DOWN,
+                let PhysicalKey::Code(keycode) = physical_key else {
+                    return
+                };
 
-                    VK::Z => Button::A,
-                    VK::X => Button::B,
+                // Only the press is taken, so the release still reaches the
+                // state, in case `Button::START` was held before Alt was.
+                if keycode == K::Enter
+                && modifiers.alt_key()
+                && element_state == ElementState::Pressed {
+                    toggle_fullscreen(&window);
+                    return
+                }
 
-                    // For those using the Dvorak layout.
-                    VK::Semicolon => Button::A,
-                    VK::Q => Button::B,
+                if let (ElementState::Pressed, Some(text)) = (element_state, &text) {
+                    for character in text.chars() {
+                        state.text(character);
+                    }
+                }
+
+                if keycode == K::F2 {
+                    if element_state == ElementState::Pressed {
+                        output_frame_buffer.scale_mode = output_frame_buffer
+                            .scale_mode
+                            .next();
+                    }
+                    return
+                }
+
+                #[cfg(feature = "post-process")]
+                if keycode == K::F3 {
+                    if element_state == ElementState::Pressed {
+                        output_frame_buffer.post_process = output_frame_buffer
+                            .post_process
+                            .next();
+                    }
+                    return
+                }
+
+                // These are where the keys are, not what they are labeled,
+                // so they end up in the same place on every layout.
+                let button = match keycode {
+                    K::Enter => Button::START,
+                    K::ShiftRight => Button::SELECT,
+                    K::ArrowUp => Button::UP,
+                    K::ArrowLeft => Button::LEFT,
+                    K::ArrowRight => Button::RIGHT,
+                    K::ArrowDown => Button::DOWN,
+
+                    K::KeyZ => Button::A,
+                    K::KeyX => Button::B,
 
                     _ => return,
                 };
@@ -92,13 +211,56 @@ pub fn run<S: State + 'static>(mut state: S) {
                 }
             }
             Event::WindowEvent {
-                event: WindowEvent::Focused(true),
+                event: WindowEvent::Focused(focused),
                 window_id,
             } if window_id == window.id() => {
-                just_gained_focus = true;
+                if focused {
+                    just_gained_focus = true;
+                    state.focus_gained();
+                } else {
+                    state.focus_lost();
+                }
             }
-            Event::MainEventsCleared => {
-                let (commands, sounds) = state.frame();
+            Event::AboutToWait => {
+                #[cfg(all(
+                    not(target_arch = "wasm32"),
+                    feature = "hot-reload",
+                    debug_assertions,
+                ))]
+                sheet_reloader.poll(&mut output_frame_buffer);
+
+                #[cfg(target_arch = "wasm32")]
+                if let Some(hidden) = visibility.take_change() {
+                    set_suspended(
+                        &mut state,
+                        &mut suspended,
+                        &mut accumulator,
+                        hidden,
+                    );
+                }
+
+                if suspended {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        loop_helper.loop_sleep();
+                        loop_helper.loop_start();
+                    }
+                    return
+                }
+
+                for _ in 0..accumulator.advance(clock.seconds()) {
+                    state.update();
+                }
+
+                let requests = state.take_requests();
+                if requests.quit {
+                    target.exit();
+                }
+                if requests.toggle_fullscreen {
+                    toggle_fullscreen(&window);
+                }
+
+                let (commands, sounds) = state.render(accumulator.interpolation());
 
                 handle_sounds(&mut sound_handler, sounds);
 
@@ -115,10 +277,11 @@ pub fn run<S: State + 'static>(mut state: S) {
 
                 if NeedsRedraw::Yes == needs_redraw
                 || just_gained_focus {
-                    graphics_context.set_buffer(
-                        &output_frame_buffer.buffer,
-                        output_frame_buffer.width,
-                        output_frame_buffer.height,
+                    present(
+                        &mut surface,
+                        &mut surface_size,
+                        &output_frame_buffer,
+                        just_gained_focus,
                     );
                 }
 
@@ -130,30 +293,136 @@ pub fn run<S: State + 'static>(mut state: S) {
                     loop_helper.loop_start();
                 }
             }
+            #[cfg(not(target_arch = "wasm32"))]
+            Event::LoopExiting => {
+                config.fullscreen = window.fullscreen().is_some();
+                config.scale_mode = output_frame_buffer.scale_mode;
+                config.save();
+            }
             _ => (),
         }
+    }).unwrap();
+}
+
+/// Shows the parts of `frame_buffer` listed in its `damage`, or all of it if
+/// `whole` is true. `surface_size` is the size `surface` was last resized to.
+fn present(
+    surface: &mut Surface<Rc<Window>, Rc<Window>>,
+    surface_size: &mut (clip::W, clip::H),
+    frame_buffer: &FrameBuffer,
+    whole: bool,
+) {
+    let size = (frame_buffer.width, frame_buffer.height);
+    let (Some(width), Some(height)) = (
+        NonZeroU32::new(size.0.into()),
+        NonZeroU32::new(size.1.into()),
+    ) else {
+        // Minimized, most likely, so there is nothing to see anyway.
+        return
+    };
+
+    let resized = *surface_size != size;
+    if resized {
+        if surface.resize(width, height).is_err() {
+            return
+        }
+        *surface_size = size;
+    }
+
+    let Ok(mut buffer) = surface.buffer_mut() else { return };
+
+    // `softbuffer` wants the top byte to be 0, where we keep the alpha.
+    let copy = |to: &mut [u32], from: &[platform_types::ARGB]| {
+        for (to, &from) in to.iter_mut().zip(from) {
+            *to = from & 0x00FF_FFFF;
+        }
+    };
+
+    // Unless the buffer holds the last frame we presented, the parts that
+    // didn't change need copying too.
+    let whole = whole || resized || buffer.age() != 1;
+
+    let result = if whole {
+        copy(&mut buffer, &frame_buffer.buffer);
+
+        buffer.present()
+    } else {
+        let row_width = usize::from(frame_buffer.width);
+        for rect in &frame_buffer.damage {
+            for y in rect.y.clone() {
+                let row = usize::from(y) * row_width;
+                let range = row + usize::from(rect.x.start)..row + usize::from(rect.x.end);
+                copy(&mut buffer[range.clone()], &frame_buffer.buffer[range]);
+            }
+        }
+
+        let damage: Vec<softbuffer::Rect> = frame_buffer.damage.iter()
+            .filter_map(|rect| Some(softbuffer::Rect {
+                x: rect.x.start.into(),
+                y: rect.y.start.into(),
+                width: NonZeroU32::new(rect.width().into())?,
+                height: NonZeroU32::new(rect.height().into())?,
+            }))
+            .collect();
+
+        buffer.present_with_damage(&damage)
+    };
+
+    // A dropped frame is not worth stopping for; the next one will
+    // probably make it.
+    let _ = result;
+}
+
+fn toggle_fullscreen(window: &Window) {
+    window.set_fullscreen(match window.fullscreen() {
+        Some(_) => None,
+        None => Some(Fullscreen::Borderless(None)),
     });
 }
 
+fn set_suspended<S: State>(
+    state: &mut S,
+    suspended: &mut bool,
+    accumulator: &mut timestep::Accumulator,
+    now_suspended: bool,
+) {
+    if *suspended == now_suspended {
+        return
+    }
+    *suspended = now_suspended;
+
+    if now_suspended {
+        state.suspend();
+    } else {
+        // Skip the time spent suspended, instead of catching up on it.
+        *accumulator = timestep::Accumulator::default();
+        state.resume();
+    }
+}
+
 #[cfg(target_arch = "wasm32")]
 mod wasm {
     use winit::{
         window::WindowBuilder,
         platform::web::WindowBuilderExtWebSys,
     };
-    use wasm_bindgen::JsCast;
+    use std::{cell::Cell, rc::Rc};
+    use wasm_bindgen::{closure::Closure, JsCast};
     use web_sys::HtmlCanvasElement;
-    use platform_types::{SFX, unscaled};
+    use platform_types::{SFX, command};
 
-    pub fn set_canvas(builder: WindowBuilder) -> WindowBuilder {
+    pub fn set_canvas(
+        builder: WindowBuilder,
+        logical_size: command::Size,
+    ) -> WindowBuilder {
         let canvas = get_canvas();
 
         // Use the size of the screen the commands pretends there is, since the
         // browser will stretch it for us.
         let size = winit::dpi::Size::Physical(
             winit::dpi::PhysicalSize::new(
-                platform_types::command::WIDTH.into(),
-                platform_types::command::HEIGHT.into(),
+                logical_size.width() as u32,
+                logical_size.height() as u32,
             ),
         );
 
@@ -181,6 +450,59 @@ mod wasm {
             .unwrap()
     }
 
+    /// Tracks whether the page is hidden, for example because another tab
+    /// was switched to. `winit` doesn't send `Suspended` or `Resumed` on
+    /// the web, so we check this each frame instead.
+    pub struct Visibility {
+        hidden: Rc<Cell<bool>>,
+        last_hidden: Cell<bool>,
+    }
+
+    impl Visibility {
+        pub fn listen() -> Self {
+            let hidden = Rc::new(Cell::new(is_hidden()));
+
+            let callback = {
+                let hidden = Rc::clone(&hidden);
+                Closure::wrap(
+                    Box::new(move || hidden.set(is_hidden())) as Box<dyn FnMut()>
+                )
+            };
+
+            if let Some(document) = web_sys::window()
+                .and_then(|window| window.document()) {
+                let _ = document.add_event_listener_with_callback(
+                    "visibilitychange",
+                    callback.as_ref().unchecked_ref(),
+                );
+            }
+            // The listener lives as long as the page does.
+            callback.forget();
+
+            Self {
+                last_hidden: Cell::new(hidden.get()),
+                hidden,
+            }
+        }
+
+        /// Returns `Some(true)` if the page was hidden since the last call,
+        /// `Some(false)` if it was shown again, and `None` otherwise.
+        pub fn take_change(&self) -> Option<bool> {
+            let hidden = self.hidden.get();
+            if hidden == self.last_hidden.replace(hidden) {
+                None
+            } else {
+                Some(hidden)
+            }
+        }
+    }
+
+    fn is_hidden() -> bool {
+        web_sys::window()
+            .and_then(|window| window.document())
+            .is_some_and(|document| document.hidden())
+    }
+
     pub type SoundHandler = ();
 
     pub fn init_sound_handler() -> SoundHandler {
@@ -217,6 +539,32 @@ mod wasm {
     }
 }
 
+/// Seconds since some fixed point in time.
+struct Clock {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+}
+
+impl Clock {
+    fn new() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn seconds(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    // `Instant::now` panics on the web.
+    #[cfg(target_arch = "wasm32")]
+    fn seconds(&self) -> f64 {
+        js_sys::Date::now() / 1000.
+    }
+}
+
 #[cfg(target_arch = "wasm32")]
 pub fn get_state_params() -> StateParams {
     use js_sys::Date;
@@ -244,15 +592,17 @@ pub fn get_state_params() -> StateParams {
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-pub fn get_state_params() -> StateParams {
-    fn logger(s: &str) {
-        println!("{}", s);
-    }
+fn logger(s: &str) {
+    println!("{}", s);
+}
 
-    fn error_logger(s: &str) {
-        eprintln!("{}", s);
-    }
+#[cfg(not(target_arch = "wasm32"))]
+fn error_logger(s: &str) {
+    eprintln!("{}", s);
+}
 
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_state_params() -> StateParams {
     (
         new_seed(),
         Some(logger),
@@ -272,12 +622,267 @@ fn new_seed() -> xs::Seed {
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+mod config {
+    //! The window settings that are remembered from one run to the next,
+    //! on desktop. The file is made of `key value` lines. Lines that can't
+    //! be understood are skipped, so a file from an older version, or one
+    //! that was edited by hand, still loads as much as it can.
+
+    use render::ScaleMode;
+    use std::path::PathBuf;
+    use winit::{
+        dpi::{PhysicalPosition, PhysicalSize},
+        window::{Fullscreen, Window, WindowBuilder},
+    };
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Config {
+        /// The size of the window the last time it was not fullscreen.
+        pub size: Option<PhysicalSize<u32>>,
+        /// Where the window was the last time it was not fullscreen.
+        pub position: Option<PhysicalPosition<i32>>,
+        pub fullscreen: bool,
+        pub scale_mode: ScaleMode,
+    }
+
+    impl Config {
+        /// Returns the default config if there is no file yet, or it can't
+        /// be read.
+        pub fn load() -> Self {
+            path()
+                .and_then(|path| std::fs::read_to_string(path).ok())
+                .map(|text| Self::parse(&text))
+                .unwrap_or_default()
+        }
+
+        pub fn save(&self) {
+            let Some(path) = path() else { return };
+
+            // Forgetting the window size is not worth bothering anyone
+            // about, so errors are ignored.
+            if let Some(dir) = path.parent() {
+                let _ = std::fs::create_dir_all(dir);
+            }
+            let _ = std::fs::write(path, self.to_text());
+        }
+
+        pub fn apply_to(&self, mut builder: WindowBuilder) -> WindowBuilder {
+            if let Some(size) = self.size {
+                builder = builder.with_inner_size(size);
+            }
+            if let Some(position) = self.position {
+                builder = builder.with_position(position);
+            }
+            if self.fullscreen {
+                builder = builder.with_fullscreen(Some(Fullscreen::Borderless(None)));
+            }
+
+            builder
+        }
+
+        pub fn window_resized(&mut self, window: &Window, size: PhysicalSize<u32>) {
+            // Minimizing can report a size of zero, which we don't want to
+            // come back to.
+            if window.fullscreen().is_none() && size.width > 0 && size.height > 0 {
+                self.size = Some(size);
+            }
+        }
+
+        pub fn window_moved(&mut self, window: &Window, position: PhysicalPosition<i32>) {
+            if window.fullscreen().is_none() {
+                self.position = Some(position);
+            }
+        }
+
+        fn parse(text: &str) -> Self {
+            let mut config = Self::default();
+            let mut width = None;
+            let mut height = None;
+            let mut x = None;
+            let mut y = None;
+
+            for line in text.lines() {
+                let Some((key, value)) = line.trim().split_once(' ') else {
+                    continue
+                };
+                let value = value.trim();
+
+                match key {
+                    "width" => width = value.parse().ok(),
+                    "height" => height = value.parse().ok(),
+                    "x" => x = value.parse().ok(),
+                    "y" => y = value.parse().ok(),
+                    "fullscreen" => if let Ok(fullscreen) = value.parse() {
+                        config.fullscreen = fullscreen;
+                    },
+                    "scale_mode" => if let Some(&scale_mode) = value.parse()
+                        .ok()
+                        .and_then(|i: usize| ScaleMode::ALL.get(i)) {
+                        config.scale_mode = scale_mode;
+                    },
+                    _ => {}
+                }
+            }
+
+            if let (Some(width), Some(height)) = (width, height) {
+                config.size = Some(PhysicalSize::new(width, height));
+            }
+            if let (Some(x), Some(y)) = (x, y) {
+                config.position = Some(PhysicalPosition::new(x, y));
+            }
+
+            config
+        }
+
+        fn to_text(self) -> String {
+            let mut text = String::new();
+
+            if let Some(size) = self.size {
+                text += &format!("width {}\nheight {}\n", size.width, size.height);
+            }
+            if let Some(position) = self.position {
+                text += &format!("x {}\ny {}\n", position.x, position.y);
+            }
+            text += &format!("fullscreen {}\n", self.fullscreen);
+
+            let scale_mode = ScaleMode::ALL.iter()
+                .position(|&mode| mode == self.scale_mode)
+                .unwrap_or(0);
+            text += &format!("scale_mode {scale_mode}\n");
+
+            text
+        }
+    }
+
+    /// Where config files usually go on each OS, or `None` if the
+    /// environment variables that say where that is are not set.
+    fn path() -> Option<PathBuf> {
+        use std::env::var_os;
+
+        let dir = if cfg!(target_os = "windows") {
+            PathBuf::from(var_os("APPDATA")?)
+        } else if cfg!(target_os = "macos") {
+            PathBuf::from(var_os("HOME")?).join("Library/Application Support")
+        } else {
+            var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?
+        };
+
+        Some(dir.join("peak").join("window.cfg"))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use render::Filter;
+
+        #[test]
+        fn configs_survive_being_written_and_read_back() {
+            for config in [
+                Config::default(),
+                Config {
+                    size: Some(PhysicalSize::new(1280, 720)),
+                    position: Some(PhysicalPosition::new(-8, 40)),
+                    fullscreen: true,
+                    scale_mode: ScaleMode::Stretch(Filter::Nearest),
+                },
+            ] {
+                assert_eq!(Config::parse(&config.to_text()), config);
+            }
+        }
+
+        #[test]
+        fn lines_that_cannot_be_understood_are_skipped() {
+            let config = Config::parse("width 640\nheight\nfullscreen yes\nvolume 3\nscale_mode 99\n");
+
+            assert_eq!(config, Config::default());
+        }
+    }
+}
+
 #[cfg(target_arch = "wasm32")]
 use wasm::{init_sound_handler, handle_sounds};
 
 #[cfg(not(target_arch = "wasm32"))]
 use not_wasm::{init_sound_handler, handle_sounds};
 
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    feature = "hot-reload",
+    debug_assertions,
+))]
+mod hot_reload {
+    use assets::load::{self, Watcher};
+    use platform_types::sprite::SheetId;
+    use render::FrameBuffer;
+    use std::time::{Duration, Instant};
+
+    /// Checking the files every frame would be wasteful, and nobody saves
+    /// an image that quickly anyway.
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Watches the image files that the embedded sprite sheets were made
+    /// from, and swaps in the new pixels whenever one is saved. This way
+    /// the art can be tweaked without rebuilding.
+    pub struct SheetReloader {
+        // One per distinct `assets::Sheet::source`, along with the ids of
+        // the sheets that come from that file.
+        watchers: Vec<(Watcher, Vec<SheetId>)>,
+        last_poll: Option<Instant>,
+    }
+
+    impl SheetReloader {
+        pub fn new() -> Self {
+            let mut watchers: Vec<(Watcher, Vec<SheetId>)> = Vec::new();
+
+            for id in SheetId::ALL {
+                let path = load::source_path(assets::sheet(id).source);
+
+                match watchers.iter_mut().find(|(w, _)| w.path() == path) {
+                    Some((_, ids)) => ids.push(id),
+                    None => watchers.push((Watcher::new(path), vec![id])),
+                }
+            }
+
+            Self {
+                watchers,
+                last_poll: None,
+            }
+        }
+
+        pub fn poll(&mut self, frame_buffer: &mut FrameBuffer) {
+            let now = Instant::now();
+            if let Some(last_poll) = self.last_poll {
+                if now.duration_since(last_poll) < POLL_INTERVAL {
+                    return
+                }
+            }
+            self.last_poll = Some(now);
+
+            for (watcher, ids) in self.watchers.iter_mut() {
+                match watcher.poll() {
+                    None => {},
+                    Some(Ok(sheet)) => {
+                        for &id in ids.iter() {
+                            frame_buffer.set_sheet(id, sheet.clone());
+                        }
+                    },
+                    Some(Err(e)) => {
+                        // Keep drawing whatever we had, since the file may
+                        // just be half written.
+                        super::error_logger(&format!(
+                            "Could not reload {}: {e}",
+                            watcher.path().display()
+                        ));
+                    },
+                }
+            }
+        }
+    }
+}
+
 #[cfg(all(
     not(target_arch = "wasm32"),
     feature = "non-web-sound"
@@ -535,7 +535,6 @@ pub const GFX_WIDTH: usize = 512;
 pub const GFX_HEIGHT: usize = 1024;
 pub const GFX_LENGTH: usize = GFX_WIDTH * GFX_HEIGHT;
 
-pub const FONT_BASE_Y: u8 = 128;
 pub const FONT_WIDTH: usize = 128;
 pub const FONT_HEIGHT: usize = 128;
 pub const FONT_LENGTH: usize = FONT_WIDTH * FONT_HEIGHT;
@@ -543,10 +542,35 @@ pub const FONT_LENGTH: usize = FONT_WIDTH * FONT_HEIGHT;
 pub type PaletteIndex = u8;
 
 
+pub mod sprites;
+
 pub mod sprite {
     pub use super::unscaled::{W, H, LengthInner};
 
     pub type Inner = u16;
+
+    /// Which sprite sheet a `sprite::XY` is a position in.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    #[repr(u8)]
+    pub enum SheetId {
+        #[default]
+        Tiles,
+        Characters,
+        Ui,
+        Font,
+    }
+
+    impl SheetId {
+        pub const COUNT: usize = 4;
+
+        pub const ALL: [SheetId; Self::COUNT] = [
+            SheetId::Tiles,
+            SheetId::Characters,
+            SheetId::Ui,
+            SheetId::Font,
+        ];
+    }
+
     #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
     pub struct X(pub Inner);
     #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
@@ -665,38 +689,108 @@ pub mod sprite {
 
 pub mod command {
     use xs::Xs;
-    use super::{ARGB, sprite, unscaled};
+    use super::{ARGB, palette, sprite, unscaled};
 
     pub type Inner = unscaled::Inner;
 
     // Small enough to fit on pretty much any reasonable device, at an aspect ratio
     // of 3:2 (1.5), which is a compromise between 4:3 (1.33...) and 16:9 (1.788...).
-    pub const WIDTH: Inner = 480;
-    pub const HEIGHT: Inner = 320;
+    pub const DEFAULT_WIDTH: Inner = 480;
+    pub const DEFAULT_HEIGHT: Inner = 320;
+
+    // The largest `Size` that can be configured. Commands are clipped to the
+    // configured `Size`, so these only need to be at least as large as any
+    // size we would want to configure.
+    pub const MAX_WIDTH: Inner = 2048;
+    pub const MAX_HEIGHT: Inner = 2048;
+
+    /// The logical resolution that the commands are drawn at, before the
+    /// result is scaled to fit the window.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Size {
+        width: Inner,
+        height: Inner,
+    }
+
+    impl Default for Size {
+        fn default() -> Self {
+            Self::DEFAULT
+        }
+    }
+
+    impl Size {
+        pub const DEFAULT: Size = Size {
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+        };
+
+        pub const MAX: Size = Size {
+            width: MAX_WIDTH,
+            height: MAX_HEIGHT,
+        };
+
+        /// Returns `None` if either side is less than 1, or more than
+        /// `MAX_WIDTH` or `MAX_HEIGHT` respectively.
+        pub const fn new(width: Inner, height: Inner) -> Option<Size> {
+            if width < 1 || width > MAX_WIDTH
+            || height < 1 || height > MAX_HEIGHT {
+                None
+            } else {
+                Some(Size { width, height })
+            }
+        }
+
+        pub const fn width(self) -> Inner {
+            self.width
+        }
+
+        pub const fn height(self) -> Inner {
+            self.height
+        }
 
-    pub const LENGTH: usize = WIDTH as usize * HEIGHT as usize;
+        pub const fn w(self) -> unscaled::W {
+            unscaled::W(self.width)
+        }
 
-    pub const WIDTH_W: unscaled::W = unscaled::W(WIDTH);
-    pub const HEIGHT_H: unscaled::H = unscaled::H(HEIGHT);
+        pub const fn h(self) -> unscaled::H {
+            unscaled::H(self.height)
+        }
+
+        /// The amount of pixels in a frame of this size.
+        pub const fn length(self) -> usize {
+            self.width as usize * self.height as usize
+        }
+    }
 
     #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
     pub struct X(unscaled::X);
 
     impl X {
         pub const ZERO: X = X(unscaled::X(0));
-        pub const MAX: X = X(unscaled::X(WIDTH - 1));
+        pub const MAX: X = X::last(Size::MAX);
 
         pub const fn get(self) -> unscaled::X {
             self.0
         }
 
+        /// The rightmost `X` inside a frame of the given size.
+        pub const fn last(size: Size) -> X {
+            X(unscaled::X(size.width - 1))
+        }
+
         pub const fn clipped(x: unscaled::X) -> X {
+            X::clipped_to(x, Size::MAX)
+        }
+
+        /// Clips `x` to be inside a frame of the given size.
+        pub const fn clipped_to(x: unscaled::X, size: Size) -> X {
+            let last = X::last(size);
             if x.0 < 0 {
                 X::ZERO
-            } else if x.0 < X::MAX.0.0 {
+            } else if x.0 < last.0.0 {
                 X(x)
             } else {
-                X::MAX
+                last
             }
         }
 
@@ -704,8 +798,12 @@ pub mod command {
             X::clipped(unscaled::X(x))
         }
 
-        pub fn gen(rng: &mut Xs) -> X {
-            X::clipped(unscaled::X(xs::range(rng, 0..WIDTH as _) as Inner))
+        /// A random `X` inside a frame of the given size.
+        pub fn gen(rng: &mut Xs, size: Size) -> X {
+            X::clipped_to(
+                unscaled::X(xs::range(rng, 0..size.width as _) as Inner),
+                size
+            )
         }
     }
 
@@ -714,19 +812,30 @@ pub mod command {
 
     impl Y {
         pub const ZERO: Y = Y(unscaled::Y(0));
-        pub const MAX: Y = Y(unscaled::Y(HEIGHT - 1));
+        pub const MAX: Y = Y::last(Size::MAX);
 
         pub const fn get(self) -> unscaled::Y {
             self.0
         }
 
+        /// The bottommost `Y` inside a frame of the given size.
+        pub const fn last(size: Size) -> Y {
+            Y(unscaled::Y(size.height - 1))
+        }
+
         pub const fn clipped(y: unscaled::Y) -> Y {
+            Y::clipped_to(y, Size::MAX)
+        }
+
+        /// Clips `y` to be inside a frame of the given size.
+        pub const fn clipped_to(y: unscaled::Y, size: Size) -> Y {
+            let last = Y::last(size);
             if y.0 < 0 {
                 Y::ZERO
-            } else if y.0 < Y::MAX.0.0 {
+            } else if y.0 < last.0.0 {
                 Y(y)
             } else {
-                Y::MAX
+                last
             }
         }
 
@@ -734,8 +843,12 @@ pub mod command {
             Y::clipped(unscaled::Y(y))
         }
 
-        pub fn gen(rng: &mut Xs) -> Y {
-            Y::clipped(unscaled::Y(xs::range(rng, 0..WIDTH as _) as Inner))
+        /// A random `Y` inside a frame of the given size.
+        pub fn gen(rng: &mut Xs, size: Size) -> Y {
+            Y::clipped_to(
+                unscaled::Y(xs::range(rng, 0..size.height as _) as Inner),
+                size
+            )
         }
     }
 
@@ -743,17 +856,22 @@ pub mod command {
     pub struct W(unscaled::W);
 
     impl W {
-        pub const MAX: W = W(unscaled::W(WIDTH - 1));
+        pub const MAX: W = W(unscaled::W(MAX_WIDTH - 1));
 
         pub const fn get(self) -> unscaled::W {
             self.0
         }
 
         pub const fn clipped(w: unscaled::W) -> W {
-            if w.0 < W::MAX.0.0 {
+            W::clipped_to(w, Size::MAX)
+        }
+
+        /// Clips `w` to be no wider than a frame of the given size.
+        pub const fn clipped_to(w: unscaled::W, size: Size) -> W {
+            if w.0 < size.width - 1 {
                 W(w)
             } else {
-                W::MAX
+                W(unscaled::W(size.width - 1))
             }
         }
 
@@ -766,17 +884,22 @@ pub mod command {
     pub struct H(unscaled::H);
 
     impl H {
-        pub const MAX: H = H(unscaled::H(WIDTH - 1));
+        pub const MAX: H = H(unscaled::H(MAX_HEIGHT - 1));
 
         pub const fn get(self) -> unscaled::H {
             self.0
         }
 
         pub const fn clipped(h: unscaled::H) -> H {
-            if h.0 < H::MAX.0.0 {
+            H::clipped_to(h, Size::MAX)
+        }
+
+        /// Clips `h` to be no taller than a frame of the given size.
+        pub const fn clipped_to(h: unscaled::H, size: Size) -> H {
+            if h.0 < size.height - 1 {
                 H(h)
             } else {
-                H::MAX
+                H(unscaled::H(size.height - 1))
             }
         }
 
@@ -997,42 +1120,153 @@ pub mod command {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn size_new_only_accepts_sizes_within_the_bounds() {
+        assert!(Size::new(320, 180).is_some());
+        assert!(Size::new(1, 1).is_some());
+        assert!(Size::new(MAX_WIDTH, MAX_HEIGHT).is_some());
+
+        assert_eq!(Size::new(0, 180), None);
+        assert_eq!(Size::new(320, 0), None);
+        assert_eq!(Size::new(-1, 180), None);
+        assert_eq!(Size::new(MAX_WIDTH + 1, 180), None);
+        assert_eq!(Size::new(320, MAX_HEIGHT + 1), None);
+    }
+
+    pub mod flip {
+        /// Which axes, if any, a sprite should be mirrored along when drawn.
+        #[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+        pub struct Flip(u8);
+
+        impl Flip {
+            pub const NONE      : Self = Self(0);
+            pub const HORIZONTAL: Self = Self(1 << 0);
+            pub const VERTICAL  : Self = Self(1 << 1);
+            pub const BOTH      : Self = Self(
+                Self::HORIZONTAL.0 | Self::VERTICAL.0
+            );
+
+            pub const fn contains(&self, other: Self) -> bool {
+                self.0 & other.0 == other.0
+            }
+
+            pub fn insert(&mut self, other: Self) {
+                self.0 |= other.0;
+            }
+
+            pub fn remove(&mut self, other: Self) {
+                self.0 &= !other.0;
+            }
+
+            pub const fn get(self) -> u8 {
+                self.0
+            }
+        }
+    }
+    pub use flip::Flip;
+
+    /// How opaque an entire command is drawn, on top of each pixel's own alpha.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Alpha(pub u8);
+
+    impl Alpha {
+        pub const TRANSPARENT: Alpha = Alpha(0);
+        pub const OPAQUE: Alpha = Alpha(255);
+    }
+
+    impl Default for Alpha {
+        fn default() -> Self {
+            Self::OPAQUE
+        }
+    }
+
+    /// Where the pixels a command draws come from.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    #[repr(u8)]
+    pub enum Kind {
+        /// The sprite at `sprite_xy` in `sheet`.
+        #[default]
+        Sprite,
+        /// Every pixel is opaque white, so with a `colour_override` the whole
+        /// rect is that colour. `sheet`, `sprite_xy`, `flip` and `palette` are
+        /// ignored.
+        Fill,
+    }
+
     #[derive(Clone, Copy, Debug, Default)]
     pub struct Command {
         pub rect: Rect,
+        /// The sheet that `sprite_xy` is in.
+        pub sheet: sprite::SheetId,
         pub sprite_xy: sprite::XY,
         pub colour_override: ARGB,
+        pub flip: Flip,
+        /// Multiplied with the red, green and blue of each drawn pixel. The
+        /// alpha byte is ignored. `0` means no tint, like `colour_override`.
+        pub tint: ARGB,
+        pub alpha: Alpha,
+        /// Which colours the pixels of the sprite, and `colour_override`,
+        /// are drawn as. See `palette::Id::recolour`.
+        pub palette: palette::Id,
+        pub kind: Kind,
     }
 
     pub struct ClippedAway;
 
     impl Command {
+        /// `clipped_to` the largest `Size` that can be configured.
         // TODO? make this the only way to construct `Command`s?
         // Bit of a hassle to update the accesses of the fields.
         pub fn clipped(
+            rect: unscaled::Rect,
+            sprite_xy: sprite::XY,
+            colour_override: ARGB,
+            flip: Flip,
+        ) -> Result<Self, ClippedAway> {
+            Self::clipped_to(Size::MAX, rect, sprite_xy, colour_override, flip)
+        }
+
+        /// A command that draws the sprite at `sprite_xy` to the part of
+        /// `rect` that is inside a frame of the given size.
+        pub fn clipped_to(
+            size: Size,
             rect: unscaled::Rect,
             mut sprite_xy: sprite::XY,
             colour_override: ARGB,
+            flip: Flip,
         ) -> Result<Self, ClippedAway> {
             // We need to adjust the sprite_xy according to the clipping
             // if any happens, so we don't end up with the sprite being
-            // misaligned.
+            // misaligned. When the sprite is flipped along an axis, the
+            // pixels clipped off the low edge of the rect come from the
+            // high edge of the sprite, and vice versa, so we adjust based
+            // on the opposite edge in that case.
+            let flip_x = flip.contains(Flip::HORIZONTAL);
+            let flip_y = flip.contains(Flip::VERTICAL);
+
+            let last_x = X::last(size);
+            let last_y = Y::last(size);
+
             let x_min = if rect.x.0 < 0 {
-                sprite_xy.x.0 = sprite_xy.x.0.saturating_add(rect.x.0.abs() as _);
+                if !flip_x {
+                    sprite_xy.x.0 = sprite_xy.x.0.saturating_add(rect.x.0.abs() as _);
+                }
                 X::ZERO
-            } else if rect.x.0 < X::MAX.0.0 {
+            } else if rect.x.0 < last_x.0.0 {
                 X(rect.x)
             } else {
-                X::MAX
+                last_x
             };
 
             let y_min = if rect.y.0 < 0 {
-                sprite_xy.y.0 = sprite_xy.y.0.saturating_add(rect.y.0.abs() as _);
+                if !flip_y {
+                    sprite_xy.y.0 = sprite_xy.y.0.saturating_add(rect.y.0.abs() as _);
+                }
                 Y::ZERO
-            } else if rect.y.0 < Y::MAX.0.0 {
+            } else if rect.y.0 < last_y.0.0 {
                 Y(rect.y)
             } else {
-                Y::MAX
+                last_y
             };
 
             let x_max = (rect.x + rect.w).get() - 1;
@@ -1044,8 +1278,15 @@ pub mod command {
                 return Err(ClippedAway)
             }
 
-            let x_max = X::clipped_inner(x_max);
-            let y_max = Y::clipped_inner(y_max);
+            if flip_x && x_max > last_x.0.0 {
+                sprite_xy.x.0 = sprite_xy.x.0.saturating_add((x_max - last_x.0.0) as _);
+            }
+            if flip_y && y_max > last_y.0.0 {
+                sprite_xy.y.0 = sprite_xy.y.0.saturating_add((y_max - last_y.0.0) as _);
+            }
+
+            let x_max = X::clipped_to(unscaled::X(x_max), size);
+            let y_max = Y::clipped_to(unscaled::Y(y_max), size);
 
             Ok(Self {
                 rect: Rect {
@@ -1054,21 +1295,174 @@ pub mod command {
                     x_max,
                     y_max,
                 },
+                sheet: sprite::SheetId::default(),
                 sprite_xy,
                 colour_override,
+                flip,
+                tint: 0,
+                alpha: Alpha::OPAQUE,
+                palette: palette::Id::default(),
+                kind: Kind::Sprite,
             })
         }
+
+        /// `filled_to` the largest `Size` that can be configured.
+        pub fn filled(
+            rect: unscaled::Rect,
+            colour: ARGB,
+        ) -> Result<Self, ClippedAway> {
+            Self::filled_to(Size::MAX, rect, colour)
+        }
+
+        /// A `Kind::Fill` command that covers the part of `rect` that is
+        /// inside a frame of the given size with `colour`. A `colour` of `0`
+        /// fills with white, since that means no `colour_override`.
+        pub fn filled_to(
+            size: Size,
+            rect: unscaled::Rect,
+            colour: ARGB,
+        ) -> Result<Self, ClippedAway> {
+            let mut command = Self::clipped_to(
+                size,
+                rect,
+                sprite::XY::default(),
+                colour,
+                Flip::NONE,
+            )?;
+            command.kind = Kind::Fill;
+            Ok(command)
+        }
+    }
+
+    #[test]
+    fn clipped_adjusts_the_sprite_xy_on_the_clipped_edge_when_not_flipped() {
+        let rect = unscaled::Rect {
+            x: unscaled::X(-3),
+            y: unscaled::Y(-5),
+            w: unscaled::W(16),
+            h: unscaled::H(16),
+        };
+
+        let Ok(command) = Command::clipped(
+            rect,
+            sprite::XY::default(),
+            0,
+            Flip::NONE,
+        ) else { panic!("ClippedAway") };
+
+        assert_eq!(command.sprite_xy.x, sprite::X(3));
+        assert_eq!(command.sprite_xy.y, sprite::Y(5));
+    }
+
+    #[test]
+    fn clipped_adjusts_the_sprite_xy_on_the_opposite_edge_when_flipped() {
+        let rect = unscaled::Rect {
+            x: unscaled::X(-3),
+            y: unscaled::Y(MAX_HEIGHT - 11),
+            w: unscaled::W(16),
+            h: unscaled::H(16),
+        };
+
+        let Ok(command) = Command::clipped(
+            rect,
+            sprite::XY::default(),
+            0,
+            Flip::BOTH,
+        ) else { panic!("ClippedAway") };
+
+        // The left edge being clipped removes pixels from the right side of
+        // the flipped sprite, which does not move where the sprite starts.
+        assert_eq!(command.sprite_xy.x, sprite::X(0));
+        // The bottom edge being clipped removes pixels from the top side of
+        // the flipped sprite.
+        assert_eq!(
+            command.sprite_xy.y,
+            sprite::Y((MAX_HEIGHT - 11 + 16 - 1 - Y::MAX.0.0) as _)
+        );
+    }
+
+    #[test]
+    fn clipped_to_clips_to_the_given_size() {
+        let size = Size::new(320, 180).unwrap();
+        let rect = unscaled::Rect {
+            x: unscaled::X(310),
+            y: unscaled::Y(-4),
+            w: unscaled::W(16),
+            h: unscaled::H(200),
+        };
+
+        let Ok(command) = Command::clipped_to(
+            size,
+            rect,
+            sprite::XY::default(),
+            0,
+            Flip::HORIZONTAL,
+        ) else { panic!("ClippedAway") };
+
+        assert_eq!(command.rect.x_min.get(), unscaled::X(310));
+        assert_eq!(command.rect.x_max.get(), unscaled::X(319));
+        assert_eq!(command.rect.y_min.get(), unscaled::Y(0));
+        assert_eq!(command.rect.y_max.get(), unscaled::Y(179));
+        // The 6 columns past the right edge come from the left side of the
+        // flipped sprite.
+        assert_eq!(command.sprite_xy.x, sprite::X(6));
+        assert_eq!(command.sprite_xy.y, sprite::Y(4));
+
+        let mut rng = xs::from_seed([1; 16]);
+        for _ in 0..100 {
+            assert!(X::gen(&mut rng, size).get() <= unscaled::X(319));
+            assert!(Y::gen(&mut rng, size).get() <= unscaled::Y(179));
+        }
     }
 }
 pub use command::Command;
 
+/// How a held button repeats, for `Input::pressed_or_repeated_this_frame`.
+/// Both are counted in updates, so the timing is the same on every
+/// platform, whatever the OS's own key repeat is set to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Repeat {
+    /// How many updates after being pressed a button first repeats.
+    pub delay: u16,
+    /// How many updates apart the repeats after the first one are.
+    pub rate: u16,
+}
+
+impl Default for Repeat {
+    fn default() -> Self {
+        Self {
+            // A quarter of a second, then 15 times a second.
+            delay: 15,
+            rate: 4,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Default, Debug)]
 pub struct Input {
     pub gamepad: Button,
     pub previous_gamepad: Button,
+    pub repeat: Repeat,
+    /// How many updates each button in `Button::ALL` has been held for,
+    /// not counting the current one.
+    held: [u16; Button::ALL.len()],
 }
 
 impl Input {
+    /// Call once at the end of each update, after everything has seen the
+    /// input for that update.
+    pub fn end_frame(&mut self) {
+        for (held, button) in self.held.iter_mut().zip(Button::ALL) {
+            *held = if self.gamepad.contains(button) {
+                held.saturating_add(1)
+            } else {
+                0
+            };
+        }
+
+        self.previous_gamepad = self.gamepad;
+    }
+
     #[allow(unused)]
     pub fn pressed_this_frame(&self, buttons: Button) -> bool {
         !self.previous_gamepad.contains(buttons) && self.gamepad.contains(buttons)
@@ -1079,33 +1473,94 @@ impl Input {
         self.previous_gamepad.contains(buttons) && !self.gamepad.contains(buttons)
     }
 
-    pub fn button_pressed_this_frame(&self) -> Option<Button> {
-        if self.pressed_this_frame(Button::A) {
-            return Some(Button::A)
+    /// How many updates `buttons` have all been held for, counting the
+    /// current one. So this is 1 the update they are pressed, and 0 if any
+    /// of them are not held.
+    pub fn held_frames(&self, buttons: Button) -> u16 {
+        if !self.gamepad.contains(buttons) {
+            return 0
         }
-        if self.pressed_this_frame(Button::B) {
-            return Some(Button::B)
-        }
-        if self.pressed_this_frame(Button::SELECT) {
-            return Some(Button::SELECT)
-        }
-        if self.pressed_this_frame(Button::START) {
-            return Some(Button::START)
-        }
-        if self.pressed_this_frame(Button::UP) {
-            return Some(Button::UP)
-        }
-        if self.pressed_this_frame(Button::DOWN) {
-            return Some(Button::DOWN)
-        }
-        if self.pressed_this_frame(Button::LEFT) {
-            return Some(Button::LEFT)
+
+        self.held.iter()
+            .zip(Button::ALL)
+            .filter(|&(_, button)| buttons.contains(button))
+            .map(|(&held, _)| held.saturating_add(1))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Like `pressed_this_frame`, but also true every so often while
+    /// `buttons` stay held, as `repeat` says. For things like moving,
+    /// where holding a direction should keep going that way.
+    pub fn pressed_or_repeated_this_frame(&self, buttons: Button) -> bool {
+        if self.pressed_this_frame(buttons) {
+            return true
         }
-        if self.pressed_this_frame(Button::RIGHT) {
-            return Some(Button::RIGHT)
+
+        let held = self.held_frames(buttons);
+        let delay = self.repeat.delay.max(1);
+        let rate = self.repeat.rate.max(1);
+
+        held > delay && (held - 1 - delay).is_multiple_of(rate)
+    }
+
+    pub fn button_pressed_this_frame(&self) -> Option<Button> {
+        Button::ALL.into_iter().find(|&button| self.pressed_this_frame(button))
+    }
+
+    /// The first button, in `Button::ALL` order, that
+    /// `pressed_or_repeated_this_frame` is true for.
+    pub fn button_pressed_or_repeated_this_frame(&self) -> Option<Button> {
+        Button::ALL.into_iter().find(|&button| self.pressed_or_repeated_this_frame(button))
+    }
+}
+
+#[test]
+fn held_buttons_repeat_after_the_delay_at_the_rate() {
+    let mut input = Input {
+        repeat: Repeat { delay: 3, rate: 2 },
+        ..Input::default()
+    };
+
+    let mut repeated_at = Vec::new();
+    input.gamepad.insert(Button::LEFT);
+    for frame in 1..=10 {
+        assert_eq!(input.held_frames(Button::LEFT), frame);
+        if input.pressed_or_repeated_this_frame(Button::LEFT) {
+            repeated_at.push(frame);
         }
-        None
+        input.end_frame();
     }
+
+    assert_eq!(repeated_at, [1, 4, 6, 8, 10]);
+
+    input.gamepad.remove(Button::LEFT);
+    assert_eq!(input.held_frames(Button::LEFT), 0);
+    assert!(!input.pressed_or_repeated_this_frame(Button::LEFT));
+    input.end_frame();
+
+    input.gamepad.insert(Button::LEFT);
+    assert!(input.pressed_or_repeated_this_frame(Button::LEFT));
+    assert_eq!(input.held_frames(Button::LEFT), 1);
+}
+
+#[test]
+fn held_frames_of_several_buttons_is_the_shortest_hold() {
+    let mut input = Input::default();
+
+    input.gamepad.insert(Button::A);
+    input.end_frame();
+    input.end_frame();
+    input.gamepad.insert(Button::B);
+
+    let both = {
+        let mut both = Button::A;
+        both.insert(Button::B);
+        both
+    };
+    assert_eq!(input.held_frames(Button::A), 3);
+    assert_eq!(input.held_frames(both), 1);
+    assert_eq!(input.held_frames(Button::START), 0);
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -1156,6 +1611,18 @@ pub mod button {
         pub const LEFT  : Self = Self(1 << 6);
         pub const RIGHT : Self = Self(1 << 7);
 
+        /// Every button, one at a time.
+        pub const ALL: [Self; 8] = [
+            Self::A,
+            Self::B,
+            Self::SELECT,
+            Self::START,
+            Self::UP,
+            Self::DOWN,
+            Self::LEFT,
+            Self::RIGHT,
+        ];
+
         pub const fn contains(&self, other: Self) -> bool {
             self.0 & other.0 == other.0
         }
@@ -1175,12 +1642,167 @@ pub type Logger = Option<fn(&str) -> ()>;
 
 pub type StateParams = ([u8; 16], Logger, Logger);
 
+/// Things the game can ask the platform to do, besides drawing and playing
+/// sounds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Requests {
+    /// Close the window and stop running. On the web, this stops the game,
+    /// but the page stays open.
+    pub quit: bool,
+    /// Switch between a borderless fullscreen window and a regular one.
+    pub toggle_fullscreen: bool,
+}
+
 pub trait State {
-    fn frame(&mut self) -> (&[Command], &[SFX]);
+    /// Called once, after the window is made, before the first `update`.
+    fn init(&mut self) {}
+
+    /// Advances the game by one tick, `timestep::UPDATE_SECONDS` long. This
+    /// is called as many times as needed to keep up with the clock,
+    /// however often frames are drawn.
+    fn update(&mut self);
+
+    /// Returns what to draw, and the sounds requested by the updates since
+    /// the last call. `interpolation` is how far through the current tick
+    /// the clock is, from 0 to 1, for drawing things partway between where
+    /// they were at the last update and where they will be at the next one.
+    fn render(&mut self, interpolation: f32) -> (&[Command], &[SFX]);
 
     fn press(&mut self, button: Button);
 
     fn release(&mut self, button: Button);
+
+    /// Called when the window stops getting input. Any buttons that were
+    /// held down will not get a matching `release` call, so they should be
+    /// treated as released now.
+    fn focus_lost(&mut self) {}
+
+    /// Called when the window starts getting input again, after
+    /// `focus_lost`.
+    fn focus_gained(&mut self) {}
+
+    /// Called when the game is about to stop being run for a while, for
+    /// example when the browser tab is hidden. `update` and `render` will
+    /// not be called until `resume` is.
+    fn suspend(&mut self) {}
+
+    /// Called when the game starts being run again, after `suspend`. The
+    /// time spent suspended is skipped, rather than caught up on.
+    fn resume(&mut self) {}
+
+    /// Called when the player tries to close the window. Return `false` to
+    /// keep running anyway, for example to ask whether to save first.
+    fn quit_requested(&mut self) -> bool {
+        true
+    }
+
+    /// Called when the window changes size, with the new size in physical
+    /// pixels. The commands are scaled to fit either way, so this is only
+    /// needed to react to the size in some other way.
+    fn resized(&mut self, _width: u32, _height: u32) {}
+
+    /// Called with each character typed, after the keyboard layout and any
+    /// input method has had its say. This is for entering text, like names
+    /// or seeds. Control characters come through here too, so backspace is
+    /// `'\u{8}'`. Keys that map to a `Button` are also passed to `press`.
+    fn text(&mut self, _character: char) {}
+
+    /// Called once a frame, after the updates. Each request is acted on
+    /// every time it is returned, so clear them once they have been taken.
+    fn take_requests(&mut self) -> Requests {
+        Requests::default()
+    }
+
+    /// The resolution that the commands are drawn at. Only asked for once,
+    /// at startup.
+    fn logical_size(&self) -> command::Size {
+        command::Size::DEFAULT
+    }
+}
+
+pub mod timestep {
+    //! Deciding when to call `State::update`, so the game runs at the same
+    //! speed however often frames are drawn.
+
+    pub const UPDATES_PER_SECOND: u32 = 60;
+    pub const UPDATE_SECONDS: f64 = 1. / UPDATES_PER_SECOND as f64;
+
+    /// If more updates than this are due at once, say after the process was
+    /// suspended, the rest are dropped, instead of the game trying to catch
+    /// up on all of them.
+    pub const MAX_UPDATES_AT_ONCE: u32 = 8;
+
+    /// Keeps track of how much time has passed that the game has not been
+    /// updated for yet.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Accumulator {
+        last_seconds: Option<f64>,
+        unsimulated: f64,
+    }
+
+    impl Accumulator {
+        /// Returns how many times to call `State::update`, given the current
+        /// time in seconds. The first call only starts the clock.
+        pub fn advance(&mut self, now_seconds: f64) -> u32 {
+            let elapsed = match self.last_seconds {
+                // Clocks aren't always monotonic, so don't go backwards.
+                Some(last) => f64::max(now_seconds - last, 0.),
+                None => 0.,
+            };
+            self.last_seconds = Some(now_seconds);
+
+            self.unsimulated += elapsed;
+
+            let mut updates = 0;
+            while self.unsimulated >= UPDATE_SECONDS {
+                self.unsimulated -= UPDATE_SECONDS;
+                updates += 1;
+
+                if updates >= MAX_UPDATES_AT_ONCE {
+                    self.unsimulated = self.unsimulated.min(UPDATE_SECONDS);
+                    break
+                }
+            }
+
+            updates
+        }
+
+        /// How far through the current tick the clock is, from 0 to 1.
+        pub fn interpolation(&self) -> f32 {
+            (self.unsimulated / UPDATE_SECONDS).clamp(0., 1.) as f32
+        }
+    }
+
+    #[test]
+    fn advance_updates_once_per_tick_of_elapsed_time() {
+        let mut accumulator = Accumulator::default();
+
+        assert_eq!(accumulator.advance(10.), 0);
+        assert_eq!(accumulator.advance(10. + UPDATE_SECONDS * 0.5), 0);
+        assert!((accumulator.interpolation() - 0.5).abs() < 0.001);
+
+        assert_eq!(accumulator.advance(10. + UPDATE_SECONDS * 3.25), 3);
+        assert!((accumulator.interpolation() - 0.25).abs() < 0.001);
+
+        // Drawing twice as often as updating.
+        let start = 10. + UPDATE_SECONDS * 3.25;
+        let mut updates = 0;
+        for i in 1..=120 {
+            updates += accumulator.advance(start + f64::from(i) * UPDATE_SECONDS * 0.5);
+        }
+        assert!((59..=61).contains(&updates), "{updates}");
+    }
+
+    #[test]
+    fn advance_does_not_try_to_catch_up_on_long_pauses_or_go_backwards() {
+        let mut accumulator = Accumulator::default();
+
+        accumulator.advance(0.);
+        assert_eq!(accumulator.advance(100.), MAX_UPDATES_AT_ONCE);
+        assert!(accumulator.advance(100. + UPDATE_SECONDS) <= 2);
+
+        assert_eq!(accumulator.advance(50.), 0);
+    }
 }
 
 // reportedly colourblind friendly colours
@@ -1212,4 +1834,159 @@ pub const PALETTE: [ARGB; 8] = [
     GREY,
     WHITE,
     BLACK,
-];
\ No newline at end of file
+];
+
+/// Other colours to draw the colours in `PALETTE` as. Each pixel of a sheet
+/// is stored along with the index of the `PALETTE` colour nearest to it. A
+/// sprite drawn with a palette other than `Base` has each of its pixels
+/// recoloured towards the colour at that index in that palette, keeping the
+/// pixel's alpha, and how light it is compared to the `PALETTE` colour. So
+/// shaded art can be recoloured without duplicating it.
+pub mod palette {
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    #[repr(u8)]
+    pub enum Id {
+        /// `PALETTE` itself.
+        #[default]
+        Base,
+        /// Darker, and bluer.
+        Night,
+        Greyscale,
+        /// The first four colours, rotated. Useful for variants of a sprite.
+        Alternate,
+    }
+
+    impl Id {
+        pub const COUNT: usize = 4;
+
+        pub const ALL: [Id; Self::COUNT] = [
+            Id::Base,
+            Id::Night,
+            Id::Greyscale,
+            Id::Alternate,
+        ];
+
+        pub fn colours(self) -> &'static [ARGB; PALETTE.len()] {
+            &PALETTES[self as usize]
+        }
+
+        /// What to draw `pixel` as with this palette, given the index of the
+        /// `PALETTE` colour it is nearest to, from `nearest`. A pixel that is
+        /// exactly the `PALETTE` colour becomes exactly the colour at the
+        /// same index in this palette. Pixels whose `PALETTE` colour this
+        /// palette keeps the same are left as they are.
+        pub fn recolour(self, pixel: ARGB, index: PaletteIndex) -> ARGB {
+            let index = usize::from(index) % PALETTE.len();
+            let from = PALETTE[index];
+            let to = self.colours()[index];
+            if from == to {
+                return pixel
+            }
+
+            let scale = luma(pixel);
+            let from_luma = luma(from);
+
+            let channel = |shift: u32| {
+                let scaled = ((to >> shift) & 0xFF) * scale / from_luma;
+                core::cmp::min(scaled, 0xFF) << shift
+            };
+
+            (pixel & 0xFF00_0000) | channel(16) | channel(8) | channel(0)
+        }
+    }
+
+    /// How light `colour` looks, from 0 to 255. The alpha byte is ignored.
+    fn luma(colour: ARGB) -> u32 {
+        let r = (colour >> 16) & 0xFF;
+        let g = (colour >> 8) & 0xFF;
+        let b = colour & 0xFF;
+
+        (r * 77 + g * 150 + b * 29) >> 8
+    }
+
+    /// The index of the colour in `PALETTE` that is closest to `colour`. The
+    /// alpha byte is ignored.
+    pub fn nearest(colour: ARGB) -> PaletteIndex {
+        let distance = |other: ARGB| {
+            let channel = |shift: u32| {
+                let difference = ((colour >> shift) & 0xFF) as i32
+                    - ((other >> shift) & 0xFF) as i32;
+                (difference * difference) as u32
+            };
+
+            channel(16) + channel(8) + channel(0)
+        };
+
+        let mut nearest = 0;
+        for (i, &other) in PALETTE.iter().enumerate().skip(1) {
+            if distance(other) < distance(PALETTE[usize::from(nearest)]) {
+                nearest = i as PaletteIndex;
+            }
+        }
+        nearest
+    }
+
+    /// Indexed by `Id`.
+    pub const PALETTES: [[ARGB; PALETTE.len()]; Id::COUNT] = [
+        PALETTE,
+        [
+            0xFF1629B1,
+            0xFF155861,
+            0xFF632447,
+            0xFF725C3A,
+            0xFF25194E,
+            0xFF283E75,
+            0xFF6B77BA,
+            0xFF0F112B,
+        ],
+        [
+            0xFF595959,
+            0xFF828282,
+            0xFF767676,
+            0xFFBFBFBF,
+            0xFF404040,
+            0xFF747474,
+            WHITE,
+            BLACK,
+        ],
+        [
+            GREEN,
+            RED,
+            YELLOW,
+            BLUE,
+            PURPLE,
+            GREY,
+            WHITE,
+            BLACK,
+        ],
+    ];
+}
+#[test]
+fn recolouring_maps_palette_colours_exactly_and_keeps_shading() {
+    for id in palette::Id::ALL {
+        for (i, &colour) in PALETTE.iter().enumerate() {
+            let index = palette::nearest(colour);
+            assert_eq!(usize::from(index), i, "{colour:08X}");
+            assert_eq!(id.recolour(colour, index), id.colours()[i], "{id:?}");
+        }
+    }
+
+    let half_transparent_shadow = 0x80_2A_46_C0;
+    let index = palette::nearest(half_transparent_shadow);
+    assert_eq!(PALETTE[usize::from(index)], BLUE);
+
+    let recoloured = palette::Id::Greyscale.recolour(half_transparent_shadow, index);
+    assert_eq!(recoloured >> 24, 0x80);
+    // Darker than the grey the blue itself becomes.
+    assert!(
+        (recoloured & 0xFF) < (palette::Id::Greyscale.colours()[0] & 0xFF),
+        "{recoloured:08X}"
+    );
+
+    assert_eq!(
+        palette::Id::Base.recolour(half_transparent_shadow, index),
+        half_transparent_shadow
+    );
+}
@@ -0,0 +1,356 @@
+//! Named regions of the sprite sheets.
+//!
+//! This file is generated by `tooling/update_gfx` from `assets/sprites.toml`.
+//! Edit that, then run the tool, instead of editing this.
+use crate::sprite::{SheetId, W, H, X, Y, XY};
+
+pub mod cube {
+    use super::*;
+
+    pub const SHEET: SheetId = SheetId::Tiles;
+    pub const XY: XY = XY { x: X(128), y: Y(0) };
+    pub const WIDTH: W = W(111);
+    pub const HEIGHT: H = H(128);
+    pub const COLUMNS: usize = 3;
+    pub const X_ADVANCE: W = W(112);
+    pub const Y_ADVANCE: H = H(128);
+    pub const FRAMES: [XY; 6] = [
+        XY { x: X(128), y: Y(0) },
+        XY { x: X(240), y: Y(0) },
+        XY { x: X(352), y: Y(0) },
+        XY { x: X(128), y: Y(128) },
+        XY { x: X(240), y: Y(128) },
+        XY { x: X(352), y: Y(128) },
+    ];
+}
+
+pub mod player {
+    use super::*;
+
+    pub const SHEET: SheetId = SheetId::Characters;
+    pub const XY: XY = XY { x: X(128), y: Y(256) };
+    pub const WIDTH: W = W(111);
+    pub const HEIGHT: H = H(128);
+    pub const COLUMNS: usize = 3;
+    pub const X_ADVANCE: W = W(112);
+    pub const Y_ADVANCE: H = H(128);
+    pub const FRAMES: [XY; 12] = [
+        XY { x: X(128), y: Y(256) },
+        XY { x: X(240), y: Y(256) },
+        XY { x: X(352), y: Y(256) },
+        XY { x: X(128), y: Y(384) },
+        XY { x: X(240), y: Y(384) },
+        XY { x: X(352), y: Y(384) },
+        XY { x: X(128), y: Y(512) },
+        XY { x: X(240), y: Y(512) },
+        XY { x: X(352), y: Y(512) },
+        XY { x: X(128), y: Y(640) },
+        XY { x: X(240), y: Y(640) },
+        XY { x: X(352), y: Y(640) },
+    ];
+}
+
+pub mod card_front {
+    use super::*;
+
+    pub const SHEET: SheetId = SheetId::Ui;
+    pub const XY: XY = XY { x: X(2), y: Y(1) };
+    pub const WIDTH: W = W(20);
+    pub const HEIGHT: H = H(30);
+    pub const COLUMNS: usize = 1;
+    pub const X_ADVANCE: W = W(20);
+    pub const Y_ADVANCE: H = H(30);
+    pub const FRAMES: [XY; 1] = [
+        XY { x: X(2), y: Y(1) },
+    ];
+}
+
+pub mod font {
+    use super::*;
+
+    pub const SHEET: SheetId = SheetId::Font;
+    pub const XY: XY = XY { x: X(0), y: Y(0) };
+    pub const WIDTH: W = W(8);
+    pub const HEIGHT: H = H(8);
+    pub const COLUMNS: usize = 16;
+    pub const X_ADVANCE: W = W(8);
+    pub const Y_ADVANCE: H = H(8);
+    pub const FRAMES: [XY; 256] = [
+        XY { x: X(0), y: Y(0) },
+        XY { x: X(8), y: Y(0) },
+        XY { x: X(16), y: Y(0) },
+        XY { x: X(24), y: Y(0) },
+        XY { x: X(32), y: Y(0) },
+        XY { x: X(40), y: Y(0) },
+        XY { x: X(48), y: Y(0) },
+        XY { x: X(56), y: Y(0) },
+        XY { x: X(64), y: Y(0) },
+        XY { x: X(72), y: Y(0) },
+        XY { x: X(80), y: Y(0) },
+        XY { x: X(88), y: Y(0) },
+        XY { x: X(96), y: Y(0) },
+        XY { x: X(104), y: Y(0) },
+        XY { x: X(112), y: Y(0) },
+        XY { x: X(120), y: Y(0) },
+        XY { x: X(0), y: Y(8) },
+        XY { x: X(8), y: Y(8) },
+        XY { x: X(16), y: Y(8) },
+        XY { x: X(24), y: Y(8) },
+        XY { x: X(32), y: Y(8) },
+        XY { x: X(40), y: Y(8) },
+        XY { x: X(48), y: Y(8) },
+        XY { x: X(56), y: Y(8) },
+        XY { x: X(64), y: Y(8) },
+        XY { x: X(72), y: Y(8) },
+        XY { x: X(80), y: Y(8) },
+        XY { x: X(88), y: Y(8) },
+        XY { x: X(96), y: Y(8) },
+        XY { x: X(104), y: Y(8) },
+        XY { x: X(112), y: Y(8) },
+        XY { x: X(120), y: Y(8) },
+        XY { x: X(0), y: Y(16) },
+        XY { x: X(8), y: Y(16) },
+        XY { x: X(16), y: Y(16) },
+        XY { x: X(24), y: Y(16) },
+        XY { x: X(32), y: Y(16) },
+        XY { x: X(40), y: Y(16) },
+        XY { x: X(48), y: Y(16) },
+        XY { x: X(56), y: Y(16) },
+        XY { x: X(64), y: Y(16) },
+        XY { x: X(72), y: Y(16) },
+        XY { x: X(80), y: Y(16) },
+        XY { x: X(88), y: Y(16) },
+        XY { x: X(96), y: Y(16) },
+        XY { x: X(104), y: Y(16) },
+        XY { x: X(112), y: Y(16) },
+        XY { x: X(120), y: Y(16) },
+        XY { x: X(0), y: Y(24) },
+        XY { x: X(8), y: Y(24) },
+        XY { x: X(16), y: Y(24) },
+        XY { x: X(24), y: Y(24) },
+        XY { x: X(32), y: Y(24) },
+        XY { x: X(40), y: Y(24) },
+        XY { x: X(48), y: Y(24) },
+        XY { x: X(56), y: Y(24) },
+        XY { x: X(64), y: Y(24) },
+        XY { x: X(72), y: Y(24) },
+        XY { x: X(80), y: Y(24) },
+        XY { x: X(88), y: Y(24) },
+        XY { x: X(96), y: Y(24) },
+        XY { x: X(104), y: Y(24) },
+        XY { x: X(112), y: Y(24) },
+        XY { x: X(120), y: Y(24) },
+        XY { x: X(0), y: Y(32) },
+        XY { x: X(8), y: Y(32) },
+        XY { x: X(16), y: Y(32) },
+        XY { x: X(24), y: Y(32) },
+        XY { x: X(32), y: Y(32) },
+        XY { x: X(40), y: Y(32) },
+        XY { x: X(48), y: Y(32) },
+        XY { x: X(56), y: Y(32) },
+        XY { x: X(64), y: Y(32) },
+        XY { x: X(72), y: Y(32) },
+        XY { x: X(80), y: Y(32) },
+        XY { x: X(88), y: Y(32) },
+        XY { x: X(96), y: Y(32) },
+        XY { x: X(104), y: Y(32) },
+        XY { x: X(112), y: Y(32) },
+        XY { x: X(120), y: Y(32) },
+        XY { x: X(0), y: Y(40) },
+        XY { x: X(8), y: Y(40) },
+        XY { x: X(16), y: Y(40) },
+        XY { x: X(24), y: Y(40) },
+        XY { x: X(32), y: Y(40) },
+        XY { x: X(40), y: Y(40) },
+        XY { x: X(48), y: Y(40) },
+        XY { x: X(56), y: Y(40) },
+        XY { x: X(64), y: Y(40) },
+        XY { x: X(72), y: Y(40) },
+        XY { x: X(80), y: Y(40) },
+        XY { x: X(88), y: Y(40) },
+        XY { x: X(96), y: Y(40) },
+        XY { x: X(104), y: Y(40) },
+        XY { x: X(112), y: Y(40) },
+        XY { x: X(120), y: Y(40) },
+        XY { x: X(0), y: Y(48) },
+        XY { x: X(8), y: Y(48) },
+        XY { x: X(16), y: Y(48) },
+        XY { x: X(24), y: Y(48) },
+        XY { x: X(32), y: Y(48) },
+        XY { x: X(40), y: Y(48) },
+        XY { x: X(48), y: Y(48) },
+        XY { x: X(56), y: Y(48) },
+        XY { x: X(64), y: Y(48) },
+        XY { x: X(72), y: Y(48) },
+        XY { x: X(80), y: Y(48) },
+        XY { x: X(88), y: Y(48) },
+        XY { x: X(96), y: Y(48) },
+        XY { x: X(104), y: Y(48) },
+        XY { x: X(112), y: Y(48) },
+        XY { x: X(120), y: Y(48) },
+        XY { x: X(0), y: Y(56) },
+        XY { x: X(8), y: Y(56) },
+        XY { x: X(16), y: Y(56) },
+        XY { x: X(24), y: Y(56) },
+        XY { x: X(32), y: Y(56) },
+        XY { x: X(40), y: Y(56) },
+        XY { x: X(48), y: Y(56) },
+        XY { x: X(56), y: Y(56) },
+        XY { x: X(64), y: Y(56) },
+        XY { x: X(72), y: Y(56) },
+        XY { x: X(80), y: Y(56) },
+        XY { x: X(88), y: Y(56) },
+        XY { x: X(96), y: Y(56) },
+        XY { x: X(104), y: Y(56) },
+        XY { x: X(112), y: Y(56) },
+        XY { x: X(120), y: Y(56) },
+        XY { x: X(0), y: Y(64) },
+        XY { x: X(8), y: Y(64) },
+        XY { x: X(16), y: Y(64) },
+        XY { x: X(24), y: Y(64) },
+        XY { x: X(32), y: Y(64) },
+        XY { x: X(40), y: Y(64) },
+        XY { x: X(48), y: Y(64) },
+        XY { x: X(56), y: Y(64) },
+        XY { x: X(64), y: Y(64) },
+        XY { x: X(72), y: Y(64) },
+        XY { x: X(80), y: Y(64) },
+        XY { x: X(88), y: Y(64) },
+        XY { x: X(96), y: Y(64) },
+        XY { x: X(104), y: Y(64) },
+        XY { x: X(112), y: Y(64) },
+        XY { x: X(120), y: Y(64) },
+        XY { x: X(0), y: Y(72) },
+        XY { x: X(8), y: Y(72) },
+        XY { x: X(16), y: Y(72) },
+        XY { x: X(24), y: Y(72) },
+        XY { x: X(32), y: Y(72) },
+        XY { x: X(40), y: Y(72) },
+        XY { x: X(48), y: Y(72) },
+        XY { x: X(56), y: Y(72) },
+        XY { x: X(64), y: Y(72) },
+        XY { x: X(72), y: Y(72) },
+        XY { x: X(80), y: Y(72) },
+        XY { x: X(88), y: Y(72) },
+        XY { x: X(96), y: Y(72) },
+        XY { x: X(104), y: Y(72) },
+        XY { x: X(112), y: Y(72) },
+        XY { x: X(120), y: Y(72) },
+        XY { x: X(0), y: Y(80) },
+        XY { x: X(8), y: Y(80) },
+        XY { x: X(16), y: Y(80) },
+        XY { x: X(24), y: Y(80) },
+        XY { x: X(32), y: Y(80) },
+        XY { x: X(40), y: Y(80) },
+        XY { x: X(48), y: Y(80) },
+        XY { x: X(56), y: Y(80) },
+        XY { x: X(64), y: Y(80) },
+        XY { x: X(72), y: Y(80) },
+        XY { x: X(80), y: Y(80) },
+        XY { x: X(88), y: Y(80) },
+        XY { x: X(96), y: Y(80) },
+        XY { x: X(104), y: Y(80) },
+        XY { x: X(112), y: Y(80) },
+        XY { x: X(120), y: Y(80) },
+        XY { x: X(0), y: Y(88) },
+        XY { x: X(8), y: Y(88) },
+        XY { x: X(16), y: Y(88) },
+        XY { x: X(24), y: Y(88) },
+        XY { x: X(32), y: Y(88) },
+        XY { x: X(40), y: Y(88) },
+        XY { x: X(48), y: Y(88) },
+        XY { x: X(56), y: Y(88) },
+        XY { x: X(64), y: Y(88) },
+        XY { x: X(72), y: Y(88) },
+        XY { x: X(80), y: Y(88) },
+        XY { x: X(88), y: Y(88) },
+        XY { x: X(96), y: Y(88) },
+        XY { x: X(104), y: Y(88) },
+        XY { x: X(112), y: Y(88) },
+        XY { x: X(120), y: Y(88) },
+        XY { x: X(0), y: Y(96) },
+        XY { x: X(8), y: Y(96) },
+        XY { x: X(16), y: Y(96) },
+        XY { x: X(24), y: Y(96) },
+        XY { x: X(32), y: Y(96) },
+        XY { x: X(40), y: Y(96) },
+        XY { x: X(48), y: Y(96) },
+        XY { x: X(56), y: Y(96) },
+        XY { x: X(64), y: Y(96) },
+        XY { x: X(72), y: Y(96) },
+        XY { x: X(80), y: Y(96) },
+        XY { x: X(88), y: Y(96) },
+        XY { x: X(96), y: Y(96) },
+        XY { x: X(104), y: Y(96) },
+        XY { x: X(112), y: Y(96) },
+        XY { x: X(120), y: Y(96) },
+        XY { x: X(0), y: Y(104) },
+        XY { x: X(8), y: Y(104) },
+        XY { x: X(16), y: Y(104) },
+        XY { x: X(24), y: Y(104) },
+        XY { x: X(32), y: Y(104) },
+        XY { x: X(40), y: Y(104) },
+        XY { x: X(48), y: Y(104) },
+        XY { x: X(56), y: Y(104) },
+        XY { x: X(64), y: Y(104) },
+        XY { x: X(72), y: Y(104) },
+        XY { x: X(80), y: Y(104) },
+        XY { x: X(88), y: Y(104) },
+        XY { x: X(96), y: Y(104) },
+        XY { x: X(104), y: Y(104) },
+        XY { x: X(112), y: Y(104) },
+        XY { x: X(120), y: Y(104) },
+        XY { x: X(0), y: Y(112) },
+        XY { x: X(8), y: Y(112) },
+        XY { x: X(16), y: Y(112) },
+        XY { x: X(24), y: Y(112) },
+        XY { x: X(32), y: Y(112) },
+        XY { x: X(40), y: Y(112) },
+        XY { x: X(48), y: Y(112) },
+        XY { x: X(56), y: Y(112) },
+        XY { x: X(64), y: Y(112) },
+        XY { x: X(72), y: Y(112) },
+        XY { x: X(80), y: Y(112) },
+        XY { x: X(88), y: Y(112) },
+        XY { x: X(96), y: Y(112) },
+        XY { x: X(104), y: Y(112) },
+        XY { x: X(112), y: Y(112) },
+        XY { x: X(120), y: Y(112) },
+        XY { x: X(0), y: Y(120) },
+        XY { x: X(8), y: Y(120) },
+        XY { x: X(16), y: Y(120) },
+        XY { x: X(24), y: Y(120) },
+        XY { x: X(32), y: Y(120) },
+        XY { x: X(40), y: Y(120) },
+        XY { x: X(48), y: Y(120) },
+        XY { x: X(56), y: Y(120) },
+        XY { x: X(64), y: Y(120) },
+        XY { x: X(72), y: Y(120) },
+        XY { x: X(80), y: Y(120) },
+        XY { x: X(88), y: Y(120) },
+        XY { x: X(96), y: Y(120) },
+        XY { x: X(104), y: Y(120) },
+        XY { x: X(112), y: Y(120) },
+        XY { x: X(120), y: Y(120) },
+    ];
+    /// How many columns of each frame, from the left, have visible pixels in
+    /// them, up to the rightmost one. Empty frames are 0 wide.
+    pub const WIDTHS: [u8; 256] = [
+        0, 8, 3, 2, 3, 3, 3, 3, 2, 3, 8, 3, 2, 3, 3, 5,
+        5, 5, 5, 5, 3, 3, 3, 2, 4, 4, 8, 7, 6, 6, 6, 6,
+        0, 2, 3, 3, 3, 3, 3, 2, 2, 3, 3, 3, 2, 3, 2, 3,
+        3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 2, 2, 3, 3, 3, 3,
+        3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 2, 3, 3, 3, 3,
+        3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 2, 3, 3, 3,
+        0, 7, 7, 8, 8, 8, 8, 8, 8, 8, 7, 8, 8, 8, 8, 8,
+        8, 8, 8, 8, 8, 8, 8, 7, 8, 8, 7, 8, 7, 7, 7, 7,
+        0, 7, 8, 8, 8, 8, 8, 8, 8, 7, 8, 8, 8, 8, 7, 8,
+        8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 7, 8, 8, 8, 8, 8,
+        8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+        8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 7, 8, 8,
+        7, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+        8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 7, 8, 8, 8,
+    ];
+}
@@ -1,15 +1,17 @@
 #![deny(clippy::shadow_unrelated)]
 use platform_types::{
     Command,
-    GFX_WIDTH,
     ARGB,
+    PaletteIndex,
     colours,
-    command::{self, Rect},
+    command::{self, Alpha, Flip, Rect},
+    palette,
     sprite,
     unscaled,
 };
+#[cfg(test)]
+use platform_types::PALETTE;
 
-use assets::GFX;
 
 pub mod clip {
     use core::ops::Range;
@@ -74,8 +76,14 @@ mod hash {
                 x_max,
                 y_max,
             },
+            sheet,
             sprite_xy,
             colour_override,
+            flip,
+            tint,
+            alpha,
+            palette,
+            kind,
         } = command;
 
         u16(hash, x_min.get().get() as u16);
@@ -83,10 +91,22 @@ mod hash {
         u16(hash, x_max.get().get() as u16);
         u16(hash, y_max.get().get() as u16);
 
+        byte(hash, sheet as u8);
+
         u16(hash, sprite_xy.x.0);
         u16(hash, sprite_xy.y.0);
 
         bytes(hash, &colour_override.to_ne_bytes());
+
+        byte(hash, flip.get());
+
+        bytes(hash, &tint.to_ne_bytes());
+
+        byte(hash, alpha.0);
+
+        byte(hash, palette as u8);
+
+        byte(hash, kind as u8);
     }
 }
 
@@ -97,44 +117,571 @@ enum CurrentCells {
     B
 }
 
+/// How much of the frame is redrawn when anything in it changes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DamageMode {
+    /// Only the tiles whose commands changed are redrawn.
+    #[default]
+    Tiles,
+    /// The whole frame is redrawn if any tile changed.
+    WholeFrame,
+}
+
+/// How the unscaled frame is fit into the output buffer.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ScaleMode {
+    /// Scale up by the largest whole number that fits, and fill the rest
+    /// with black bars. If the output is smaller than the unscaled frame,
+    /// this acts like `Fit(Filter::Bilinear)` instead.
+    #[default]
+    Integer,
+    /// Scale by the largest amount that fits while preserving the aspect
+    /// ratio, and fill the rest with black bars.
+    Fit(Filter),
+    /// Scale to fill the whole output, ignoring the aspect ratio.
+    Stretch(Filter),
+}
+
+impl ScaleMode {
+    pub const ALL: [ScaleMode; 7] = [
+        ScaleMode::Integer,
+        ScaleMode::Fit(Filter::SharpBilinear),
+        ScaleMode::Fit(Filter::Bilinear),
+        ScaleMode::Fit(Filter::Nearest),
+        ScaleMode::Stretch(Filter::SharpBilinear),
+        ScaleMode::Stretch(Filter::Bilinear),
+        ScaleMode::Stretch(Filter::Nearest),
+    ];
+
+    /// The mode after this one in `ALL`, wrapping around.
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|&m| m == self).unwrap_or(0);
+
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
+
+/// How output pixels are made from the unscaled pixels when scaling up by a
+/// non-integer amount. Scaling down always averages all the unscaled pixels
+/// under each output pixel, so none of them get skipped.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    /// Use the closest unscaled pixel. Sharp, but the unscaled pixels end up
+    /// being different sizes.
+    Nearest,
+    /// Blend the closest unscaled pixels. Even, but blurry.
+    Bilinear,
+    /// Use the closest unscaled pixel, except at the edges between them,
+    /// where they are blended. Mostly sharp, and mostly even.
+    #[default]
+    SharpBilinear,
+}
+
 pub struct FrameBuffer {
     pub buffer: Vec<ARGB>,
-    pub unscaled_buffer: Box<[ARGB; command::LENGTH]>,
+    /// The frame at the logical resolution, with some padding at the end.
+    pub unscaled_buffer: Vec<ARGB>,
     pub width: clip::W,
     pub height: clip::H,
     pub cells: HashCells,
+    pub damage_mode: DamageMode,
+    pub scale_mode: ScaleMode,
+    #[cfg(feature = "post-process")]
+    pub post_process: post::PostProcess,
+    /// The parts of `buffer` that changed during the last call to `render`.
+    pub damage: Vec<clip::Rect>,
+    /// The parts of `unscaled_buffer` that need to be redrawn this frame.
+    dirty: Vec<clip::Rect>,
+    logical_size: command::Size,
+    viewport: Viewport,
+    previous_size: (clip::W, clip::H),
+    previous_scale_mode: ScaleMode,
+    #[cfg(feature = "post-process")]
+    previous_post_process: post::PostProcess,
+    /// Sheets to draw from instead of the embedded ones, indexed by
+    /// `sprite::SheetId`.
+    sheet_overrides: [Option<assets::OwnedSheet>; sprite::SheetId::COUNT],
+    sheets_changed: bool,
 }
 
 impl FrameBuffer {
-    pub fn from_size((width, height): (clip::W, clip::H)) -> Self {
+    /// Makes a frame buffer for the given output size, that expects the
+    /// default logical resolution.
+    pub fn from_size(size: (clip::W, clip::H)) -> Self {
+        Self::new(size, command::Size::DEFAULT)
+    }
+
+    pub fn new(
+        (width, height): (clip::W, clip::H),
+        logical_size: command::Size,
+    ) -> Self {
+        let (tile_count_w, tile_count_h) = tile::counts(logical_size);
+        let tile_count = tile_count_w * tile_count_h;
+
         Self {
             buffer: Vec::with_capacity(
                 usize::from(width) * usize::from(height)
             ),
-            unscaled_buffer: Box::new([0; command::LENGTH]),
+            // The padding lets `draw_command` do wide loads and stores that
+            // start in the last few pixels of the frame.
+            unscaled_buffer: vec![0; logical_size.length() + wide::WIDTH as usize],
             width,
             height,
-            cells: HashCells::default(),
+            cells: HashCells::new(logical_size),
+            damage_mode: DamageMode::default(),
+            scale_mode: ScaleMode::default(),
+            #[cfg(feature = "post-process")]
+            post_process: post::PostProcess::default(),
+            damage: Vec::with_capacity(tile_count),
+            dirty: Vec::with_capacity(tile_count),
+            logical_size,
+            viewport: Viewport::new(ScaleMode::default(), (0, 0), logical_size),
+            previous_size: (0, 0),
+            previous_scale_mode: ScaleMode::default(),
+            #[cfg(feature = "post-process")]
+            previous_post_process: post::PostProcess::default(),
+            sheet_overrides: Default::default(),
+            sheets_changed: false,
+        }
+    }
+
+    /// Draws from `sheet` instead of the embedded sheet with the given id,
+    /// starting with the next call to `render`, which redraws everything.
+    /// Sprites that go past the edges of the new sheet are drawn as
+    /// transparent, so the new sheet can be a different size.
+    pub fn set_sheet(&mut self, id: sprite::SheetId, sheet: assets::OwnedSheet) {
+        self.sheet_overrides[id as usize] = Some(sheet);
+        self.sheets_changed = true;
+    }
+
+    /// The resolution that the commands are drawn at, before scaling.
+    pub fn logical_size(&self) -> command::Size {
+        self.logical_size
+    }
+}
+
+/// Effects applied to the frame after it is drawn, to make it look more
+/// like it would on an old screen.
+#[cfg(feature = "post-process")]
+pub mod post {
+    use super::*;
+
+    /// Each strength goes from 0, which turns that effect off, to 1.
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct PostProcess {
+        /// Darkens the gaps between the rows of unscaled pixels.
+        pub scanlines: f32,
+        /// Dims all but one of red, green or blue in each column of output
+        /// pixels, in turn, like the stripes of a CRT's aperture grille.
+        pub aperture_grille: f32,
+        /// Darkens the edges, and the corners most of all.
+        pub vignette: f32,
+        /// How many levels each colour channel of the unscaled pixels is
+        /// reduced to, with ordered dithering to hide the steps between them.
+        /// Values below 2 leave the colours alone.
+        pub dither_levels: u8,
+    }
+
+    impl PostProcess {
+        pub const OFF: Self = Self {
+            scanlines: 0.,
+            aperture_grille: 0.,
+            vignette: 0.,
+            dither_levels: 0,
+        };
+
+        pub const CRT: Self = Self {
+            scanlines: 0.5,
+            aperture_grille: 0.15,
+            vignette: 0.4,
+            dither_levels: 0,
+        };
+
+        pub const DITHERED: Self = Self {
+            dither_levels: 4,
+            .. Self::OFF
+        };
+
+        pub const ALL: [PostProcess; 3] = [
+            Self::OFF,
+            Self::CRT,
+            Self::DITHERED,
+        ];
+
+        /// The post process after this one in `ALL`, wrapping around.
+        pub fn next(self) -> Self {
+            let i = Self::ALL.iter().position(|&p| p == self).unwrap_or(0);
+
+            Self::ALL[(i + 1) % Self::ALL.len()]
+        }
+    }
+
+    /// The thresholds for a 4x4 ordered dither, in the range `0..16`.
+    const BAYER: [[u8; 4]; 4] = [
+        [ 0,  8,  2, 10],
+        [12,  4, 14,  6],
+        [ 3, 11,  1,  9],
+        [15,  7, 13,  5],
+    ];
+
+    /// Reduces the colours in the `dirty` part of `unscaled_buffer` to
+    /// `levels` levels per channel.
+    pub(crate) fn dither(
+        unscaled_buffer: &mut [ARGB],
+        logical_size: command::Size,
+        levels: u8,
+        dirty: &clip::Rect,
+    ) {
+        if levels < 2 {
+            return
+        }
+
+        let max_level = f32::from(levels - 1);
+        let step = 255. / max_level;
+
+        let reduce = |channel: u32, threshold: f32| -> u32 {
+            let level = (channel & 255) as f32 / step + threshold;
+
+            (level.round().clamp(0., max_level) * step).round() as u32
+        };
+
+        for y in dirty.y.clone() {
+            let row_start = usize::from(y) * logical_size.width() as usize;
+            let bayer_row = &BAYER[usize::from(y) & 3];
+
+            for x in dirty.x.clone() {
+                let threshold = (f32::from(bayer_row[usize::from(x) & 3]) + 0.5)
+                    / 16. - 0.5;
+
+                let pixel = &mut unscaled_buffer[row_start + usize::from(x)];
+
+                *pixel = (*pixel & 0xFF00_0000)
+                    | reduce(*pixel >> 16, threshold) << 16
+                    | reduce(*pixel >> 8, threshold) << 8
+                    | reduce(*pixel, threshold);
+            }
+        }
+    }
+
+    /// Applies the effects that work on output pixels to the `damaged` part
+    /// of `buffer`, which has the frame at `viewport`.
+    pub(crate) fn apply(
+        buffer: &mut [ARGB],
+        width: clip::W,
+        post_process: &PostProcess,
+        logical_size: command::Size,
+        viewport: &clip::Rect,
+        damaged: &clip::Rect,
+    ) {
+        let &PostProcess {
+            scanlines,
+            aperture_grille,
+            vignette,
+            dither_levels: _,
+        } = post_process;
+
+        if (scanlines == 0. && aperture_grille == 0. && vignette == 0.)
+        || viewport.width() == 0
+        || viewport.height() == 0 {
+            return
+        }
+
+        let width = usize::from(width);
+
+        let viewport_w = f32::from(viewport.width());
+        let viewport_h = f32::from(viewport.height());
+        let rows_per_pixel = f32::from(logical_size.height()) / viewport_h;
+
+        let scale = |channel: u32, factor: f32| -> u32 {
+            ((channel & 255) as f32 * factor) as u32
+        };
+
+        for y in damaged.y.clone() {
+            let from_top = f32::from(y - viewport.y.start) + 0.5;
+
+            // Brightest in the middle of each row of unscaled pixels.
+            let in_row = (from_top * rows_per_pixel).fract() * 2. - 1.;
+            let row_factor = 1. - scanlines * in_row * in_row;
+
+            let from_centre_y = from_top / viewport_h * 2. - 1.;
+
+            let row_start = usize::from(y) * width;
+
+            for x in damaged.x.clone() {
+                let from_left = f32::from(x - viewport.x.start) + 0.5;
+                let from_centre_x = from_left / viewport_w * 2. - 1.;
+
+                let distance_squared = (
+                    from_centre_x * from_centre_x
+                    + from_centre_y * from_centre_y
+                ) * 0.5;
+                let factor = row_factor * (1. - vignette * distance_squared);
+
+                let dimmed = factor * (1. - aperture_grille);
+                let (r_factor, g_factor, b_factor) = match (x - viewport.x.start) % 3 {
+                    0 => (factor, dimmed, dimmed),
+                    1 => (dimmed, factor, dimmed),
+                    _ => (dimmed, dimmed, factor),
+                };
+
+                let Some(pixel) = buffer.get_mut(row_start + usize::from(x)) else {
+                    continue
+                };
+
+                *pixel = (*pixel & 0xFF00_0000)
+                    | scale(*pixel >> 16, r_factor) << 16
+                    | scale(*pixel >> 8, g_factor) << 8
+                    | scale(*pixel, b_factor);
+            }
+        }
+    }
+}
+
+/// Where in the output buffer the unscaled frame goes, and how it gets
+/// scaled to fit there.
+struct Viewport {
+    rect: clip::Rect,
+    scaling: Scaling,
+}
+
+enum Scaling {
+    Integer(u16),
+    Filtered {
+        x: Axis,
+        y: Axis,
+    },
+}
+
+impl Viewport {
+    fn new(
+        mode: ScaleMode,
+        (width, height): (clip::W, clip::H),
+        logical_size: command::Size,
+    ) -> Self {
+        let frame_w = logical_size.width() as u32;
+        let frame_h = logical_size.height() as u32;
+        let w = u32::from(width);
+        let h = u32::from(height);
+
+        let centred = |inner_w: u32, inner_h: u32| {
+            let left_bar_width = (w - inner_w).div_ceil(2);
+            let top_bar_height = (h - inner_h).div_ceil(2);
+
+            clip::Rect {
+                x: left_bar_width as clip::X
+                    ..(left_bar_width + inner_w) as clip::X,
+                y: top_bar_height as clip::Y
+                    ..(top_bar_height + inner_h) as clip::Y,
+            }
+        };
+
+        let fit = || {
+            // Compare the aspect ratios without dividing.
+            if w * frame_h <= h * frame_w {
+                centred(w, (w * frame_h + frame_w / 2) / frame_w)
+            } else {
+                centred((h * frame_w + frame_h / 2) / frame_h, h)
+            }
+        };
+
+        let (rect, filter) = match mode {
+            ScaleMode::Integer => {
+                let multiplier = core::cmp::min(w / frame_w, h / frame_h);
+
+                if multiplier > 0 {
+                    return Self {
+                        rect: centred(frame_w * multiplier, frame_h * multiplier),
+                        scaling: Scaling::Integer(multiplier as u16),
+                    };
+                }
+
+                (fit(), Filter::Bilinear)
+            },
+            ScaleMode::Fit(filter) => (fit(), filter),
+            ScaleMode::Stretch(filter) => (centred(w, h), filter),
+        };
+
+        let scaling = Scaling::Filtered {
+            x: Axis::new(frame_w as u16, rect.width(), filter),
+            y: Axis::new(frame_h as u16, rect.height(), filter),
+        };
+
+        Self {
+            rect,
+            scaling,
+        }
+    }
+}
+
+/// For each output pixel along one axis, which unscaled pixels it is made
+/// from, and how much each of them counts.
+struct Axis {
+    /// How many unscaled pixels each output pixel is made from.
+    taps: usize,
+    /// The first unscaled pixel for each output pixel. The rest follow it,
+    /// and are clamped to the last unscaled pixel when read.
+    starts: Vec<u16>,
+    /// `taps` weights for each output pixel, that sum to 1.
+    weights: Vec<f32>,
+}
+
+impl Axis {
+    fn new(src_len: u16, dest_len: u16, filter: Filter) -> Self {
+        let scale = f32::from(dest_len) / f32::from(src_len);
+
+        let mut starts = Vec::with_capacity(usize::from(dest_len));
+        let mut weights = Vec::new();
+
+        if dest_len == 0 {
+            return Self { taps: 1, starts, weights }
+        }
+
+        if scale < 1. {
+            // Average all the unscaled pixels under each output pixel.
+            let covered = 1. / scale;
+            let taps = covered.ceil() as usize + 1;
+
+            for i in 0..dest_len {
+                let from = f32::from(i) * covered;
+                let to = from + covered;
+                let start = from.floor();
+
+                starts.push(start as u16);
+                for tap in 0..taps {
+                    let pixel = start + tap as f32;
+                    let overlap = to.min(pixel + 1.) - from.max(pixel);
+
+                    weights.push(overlap.max(0.) * scale);
+                }
+            }
+
+            return Self { taps, starts, weights }
+        }
+
+        if let Filter::Nearest = filter {
+            for i in 0..dest_len {
+                let centre = (f32::from(i) + 0.5) / scale;
+
+                starts.push(centre as u16);
+                weights.push(1.);
+            }
+
+            return Self { taps: 1, starts, weights }
         }
+
+        for i in 0..dest_len {
+            // In unscaled pixels, where the centres of the pixels are at 0.5.
+            let texel = (f32::from(i) + 0.5) / scale;
+
+            let texel = match filter {
+                Filter::SharpBilinear => {
+                    // Only blend within half an output pixel of the edges
+                    // of the unscaled pixels. Based on the
+                    // "sharp-bilinear-simple" shader from libretro.
+                    let floor = texel.floor();
+                    let from_centre = texel - floor - 0.5;
+                    let region = 0.5 - 0.5 / scale;
+
+                    floor
+                    + (from_centre - from_centre.clamp(-region, region)) * scale
+                    + 0.5
+                },
+                Filter::Nearest | Filter::Bilinear => texel,
+            };
+
+            let position = (texel - 0.5).max(0.);
+            let start = position.floor();
+
+            starts.push(start as u16);
+            weights.push(1. - (position - start));
+            weights.push(position - start);
+        }
+
+        Self { taps: 2, starts, weights }
+    }
+
+    /// The output pixels that are made from any of the given unscaled pixels.
+    fn range_touching(&self, src: &core::ops::Range<u16>) -> core::ops::Range<usize> {
+        let taps = self.taps;
+
+        self.starts.partition_point(|&s| usize::from(s) + taps <= usize::from(src.start))
+        ..self.starts.partition_point(|&s| s < src.end)
+    }
+}
+
+pub mod tile {
+    use super::*;
+
+    /// The length of a side of a tile, in unscaled pixels.
+    pub const SIZE: u16 = 32;
+
+    /// How many tiles it takes to cover a frame of the given size,
+    /// horizontally and vertically.
+    pub fn counts(size: command::Size) -> (usize, usize) {
+        let size_usize = usize::from(SIZE);
+
+        (
+            (size.width() as usize).div_ceil(size_usize),
+            (size.height() as usize).div_ceil(size_usize),
+        )
+    }
+
+    /// The tiles that the given rect overlaps any part of, out of the given
+    /// counts of tiles.
+    pub fn range(
+        rect: &Rect,
+        (count_w, count_h): (usize, usize),
+    ) -> (core::ops::Range<usize>, core::ops::Range<usize>) {
+        let x_min = usize::from(i16::from(rect.x_min) as u16 / SIZE);
+        let y_min = usize::from(i16::from(rect.y_min) as u16 / SIZE);
+        let x_max = usize::from(i16::from(rect.x_max) as u16 / SIZE);
+        let y_max = usize::from(i16::from(rect.y_max) as u16 / SIZE);
+
+        (
+            core::cmp::min(x_min, count_w)..core::cmp::min(x_max + 1, count_w),
+            core::cmp::min(y_min, count_h)..core::cmp::min(y_max + 1, count_h),
+        )
     }
 }
 
-#[derive(Default)]
+type Cells = [hash::Cell];
+
 pub struct HashCells {
     current_cells: CurrentCells,
-    cells_a: hash::Cell,
-    cells_b: hash::Cell,
+    cells_a: Box<Cells>,
+    cells_b: Box<Cells>,
+    logical_size: command::Size,
+    counts: (usize, usize),
+}
+
+impl Default for HashCells {
+    fn default() -> Self {
+        Self::new(command::Size::DEFAULT)
+    }
 }
 
 impl HashCells {
-    fn current_mut(&mut self) -> &mut hash::Cell {
+    fn new(logical_size: command::Size) -> Self {
+        let counts = tile::counts(logical_size);
+        let count = counts.0 * counts.1;
+
+        Self {
+            current_cells: <_>::default(),
+            cells_a: vec![<_>::default(); count].into_boxed_slice(),
+            cells_b: vec![<_>::default(); count].into_boxed_slice(),
+            logical_size,
+            counts,
+        }
+    }
+
+    fn current_mut(&mut self) -> &mut Cells {
         match self.current_cells {
             CurrentCells::A => &mut self.cells_a,
             CurrentCells::B => &mut self.cells_b,
         }
     }
 
-    fn current_and_prev(&self) -> (&hash::Cell, &hash::Cell) {
+    fn current_and_prev(&self) -> (&Cells, &Cells) {
         match self.current_cells {
             CurrentCells::A => (&self.cells_a, &self.cells_b),
             CurrentCells::B => (&self.cells_b, &self.cells_a),
@@ -153,14 +700,91 @@ impl HashCells {
         commands: &[Command],
         (w, h): (u16, u16),
     ) {
+        let counts = self.counts;
         let cells = self.current_mut();
-        *cells = <_>::default();
 
-        hash::u16(cells, w);
-        hash::u16(cells, h);
+        for cell in cells.iter_mut() {
+            *cell = <_>::default();
+
+            hash::u16(cell, w);
+            hash::u16(cell, h);
+        }
 
         for command in commands {
-            hash::command(cells, command);
+            let (tiles_x, tiles_y) = tile::range(&command.rect, counts);
+
+            for tile_y in tiles_y {
+                for tile_x in tiles_x.clone() {
+                    hash::command(
+                        &mut cells[tile_y * counts.0 + tile_x],
+                        command
+                    );
+                }
+            }
+        }
+    }
+
+    /// Pushes rects, in unscaled pixels, covering every tile that differs
+    /// between the current and previous cells. Vertically adjacent runs of
+    /// tiles with the same horizontal extent are merged.
+    fn push_dirty_rects(
+        &self,
+        mode: DamageMode,
+        rects: &mut Vec<clip::Rect>,
+    ) {
+        let (cells, cells_prev) = self.current_and_prev();
+        let (count_w, count_h) = self.counts;
+        let width = self.logical_size.width() as usize;
+        let height = self.logical_size.height() as usize;
+
+        let tile_rect = |
+            tiles_x: core::ops::Range<usize>,
+            tile_y: usize,
+        | {
+            let size = usize::from(tile::SIZE);
+
+            clip::Rect {
+                x: (tiles_x.start * size) as clip::X
+                    ..core::cmp::min(tiles_x.end * size, width) as clip::X,
+                y: (tile_y * size) as clip::Y
+                    ..core::cmp::min((tile_y + 1) * size, height) as clip::Y,
+            }
+        };
+
+        for tile_y in 0..count_h {
+            let row_start = tile_y * count_w;
+
+            let mut tile_x = 0;
+            while tile_x < count_w {
+                let i = row_start + tile_x;
+                if cells[i] == cells_prev[i] {
+                    tile_x += 1;
+                    continue
+                }
+
+                if mode == DamageMode::WholeFrame {
+                    rects.push(tile_rect(0..count_w, 0));
+                    let last = rects.len() - 1;
+                    rects[last].y.end = height as clip::Y;
+                    return
+                }
+
+                let run_start = tile_x;
+                while tile_x < count_w
+                && cells[row_start + tile_x] != cells_prev[row_start + tile_x] {
+                    tile_x += 1;
+                }
+
+                let run = tile_rect(run_start..tile_x, tile_y);
+
+                if let Some(above) = rects.iter_mut().find(|r|
+                    r.x == run.x && r.y.end == run.y.start
+                ) {
+                    above.y.end = run.y.end;
+                } else {
+                    rects.push(run);
+                }
+            }
         }
     }
 }
@@ -209,7 +833,7 @@ mod reset_then_hash_commands_around_a_swap_produces_identical_current_and_prev_c
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NeedsRedraw {
     No,
     Yes
@@ -324,14 +948,13 @@ mod wide {
             $e1: expr,
             $e2: expr,
             $e3: expr $(,)?
-        ) => ({#[allow(unused_unsafe)]
+        ) => ({
+            // Evaluated outside the `unsafe` block, so the arguments
+            // can't sneak unsafe code in.
+            let (e0, e1, e2, e3) = ($e0, $e1, $e2, $e3);
+            #[allow(unused_unsafe)]
             unsafe {
-                core::arch::x86_64::_mm_set_epi32(
-                    $e3,
-                    $e2,
-                    $e1,
-                    $e0,
-                )
+                core::arch::x86_64::_mm_set_epi32(e3, e2, e1, e0)
             }
         });
     }
@@ -433,9 +1056,11 @@ mod wide {
     macro_rules! _i32 {
         (
             $a: expr $(,)?
-        ) => ({#[allow(unused_unsafe)]
+        ) => ({
+            let a = $a;
+            #[allow(unused_unsafe)]
             unsafe {
-                core::arch::x86_64::_mm_set1_epi32($a)
+                core::arch::x86_64::_mm_set1_epi32(a)
             }
         });
     }
@@ -445,9 +1070,11 @@ mod wide {
     macro_rules! _f32 {
         (
             $a: expr $(,)?
-        ) => ({#[allow(unused_unsafe)]
+        ) => ({
+            let a = $a;
+            #[allow(unused_unsafe)]
             unsafe {
-                core::arch::x86_64::_mm_set_ps1($a)
+                core::arch::x86_64::_mm_set_ps1(a)
             }
         });
     }
@@ -547,9 +1174,11 @@ mod wide {
         (
             $lhs: expr,
             $rhs: expr $(,)?
-        ) => ({#[allow(unused_unsafe)]
+        ) => ({
+            let (lhs, rhs) = ($lhs, $rhs);
+            #[allow(unused_unsafe)]
             unsafe {
-                core::arch::x86_64::_mm_cmpeq_epi32($lhs, $rhs)
+                core::arch::x86_64::_mm_cmpeq_epi32(lhs, rhs)
             }
         });
     }
@@ -567,6 +1196,18 @@ mod wide {
         });
     }
     pub use _gt_mask_32 as gt_mask_32;
+
+    #[macro_export]
+    macro_rules! _reverse_32 {
+        (
+            $a: expr $(,)?
+        ) => ({#[allow(unused_unsafe)]
+            unsafe {
+                core::arch::x86_64::_mm_shuffle_epi32($a, 0b00_01_10_11)
+            }
+        });
+    }
+    pub use _reverse_32 as reverse_32;
 }
 
 #[cfg(all(
@@ -762,7 +1403,12 @@ mod wide {
             $a: expr $(,)?
         ) => ({#[allow(unused_unsafe)]
             unsafe {
-                core::arch::wasm32::u32x4_trunc_sat_f32x4($a)
+                // `_mm_cvtps_epi32` rounds to the nearest integer, with ties
+                // going to even, by default. So we do the same here, so we
+                // get the same results on both targets.
+                core::arch::wasm32::i32x4_trunc_sat_f32x4(
+                    core::arch::wasm32::f32x4_nearest($a)
+                )
             }
         });
     }
@@ -774,7 +1420,8 @@ mod wide {
             $a: expr $(,)?
         ) => ({#[allow(unused_unsafe)]
             unsafe {
-                core::arch::wasm32::f32x4_convert_u32x4($a)
+                // `_mm_cvtepi32_ps` treats its input as signed, so we do too.
+                core::arch::wasm32::f32x4_convert_i32x4($a)
             }
         });
     }
@@ -924,534 +1571,2086 @@ mod wide {
         });
     }
     pub use _gt_mask_32 as gt_mask_32;
+
+    #[macro_export]
+    macro_rules! _reverse_32 {
+        (
+            $a: expr $(,)?
+        ) => ({#[allow(unused_unsafe)]
+            unsafe {
+                let a = $a;
+                core::arch::wasm32::i32x4_shuffle::<3, 2, 1, 0>(a, a)
+            }
+        });
+    }
+    pub use _reverse_32 as reverse_32;
 }
 
-#[cfg(not(any(
-    all(
-        target_arch = "x86_64",
-        target_feature = "sse",
-        target_feature = "sse2",
-    ),
-    all(
-        target_arch = "wasm32",
-        target_feature = "simd128",
-    ),
-)))]
-mod wide {
+/// A portable version of `wide`, with each lane stored in an array. This is
+/// used when no SIMD version is available for the target, and in tests, to
+/// check the SIMD versions against.
+#[cfg(any(
+    test,
+    not(any(
+        all(
+            target_arch = "x86_64",
+            target_feature = "sse",
+            target_feature = "sse2",
+        ),
+        all(
+            target_arch = "wasm32",
+            target_feature = "simd128",
+        ),
+    )),
+))]
+mod wide_scalar {
     use super::*;
     pub const WIDTH: unscaled::Inner = 4;
 
-    compile_error!("wide not implemented for this target");
-}
-
-#[cfg(test)]
-mod wide_tests {
-    use super::wide::*;
-
-    /// Thses names chosen to be the same length as `0`.
-    const A: i32 = 0xAAAA_AAAAu32 as i32;
-    const B: i32 = 0xBBBB_BBBBu32 as i32;
-    const C: i32 = 0xCCCC_CCCCu32 as i32;
-    const D: i32 = 0xDDDD_DDDDu32 as i32;
-    const F: i32 = 0xFFFF_FFFFu32 as i32;
-
-    macro_rules! to_array {
-        ($v: expr) => ({
-            let mut arr = [0; WIDTH as usize];
-            let v = $v;
-
-            // SAFETY: The pointers produced by the code generated by
-            // these macros are valid to read 128 bytes from.
-            unsafe {
-                store!(v, &mut arr);
-            }
+    pub type I = [i32; WIDTH as usize];
+    pub type F = [f32; WIDTH as usize];
 
-            arr
-        })
+    pub fn map_i(a: I, f: impl Fn(i32) -> i32) -> I {
+        [f(a[0]), f(a[1]), f(a[2]), f(a[3])]
     }
 
-    #[test]
-    fn pick_via_mask_returns_the_expected_result_in_this_or_like_case() {
-        let left = i32x4!(A, B, 0, 0);
-        let right = i32x4!(0, 0, C, D);
-        let mask = i32x4!(0, 0, F, F);
-
-        let actual = pick_via_mask!(
-            left,
-            right,
-            mask,
-        );
-
-        assert_eq!(
-            to_array!(actual),
-            to_array!(i32x4!(A, B, C, D))
-        );
+    pub fn zip_i(a: I, b: I, f: impl Fn(i32, i32) -> i32) -> I {
+        [f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2]), f(a[3], b[3])]
     }
-}
 
-pub fn render(
-    frame_buffer: &mut FrameBuffer,
-    commands: &[Command],
-) -> NeedsRedraw {
-    if frame_buffer.width < command::WIDTH as u16
-    || frame_buffer.height < command::HEIGHT as u16 {
-        frame_buffer.width = command::WIDTH as u16;
-        frame_buffer.height = command::HEIGHT as u16;
+    pub fn map_f(a: F, f: impl Fn(f32) -> f32) -> F {
+        [f(a[0]), f(a[1]), f(a[2]), f(a[3])]
     }
 
-    let width_multiplier = frame_buffer.width / command::WIDTH as u16;
-    let height_multiplier = frame_buffer.height / command::HEIGHT as u16;
-    let multiplier = core::cmp::min(width_multiplier, height_multiplier);
-    if multiplier == 0 {
-        debug_assert!(multiplier != 0);
-        return NeedsRedraw::No;
+    pub fn zip_f(a: F, b: F, f: impl Fn(f32, f32) -> f32) -> F {
+        [f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2]), f(a[3], b[3])]
     }
 
-    let vertical_bars_width: clip::W = frame_buffer.width - (multiplier * command::WIDTH as u16);
-
-    let left_bar_width: clip::W = (vertical_bars_width + 1) / 2;
-
-    let right_bar_width: clip::W = vertical_bars_width / 2;
-
-    let horizontal_bars_height: clip::H = frame_buffer.height - (multiplier * command::HEIGHT as u16);
-
-    let top_bar_height: clip::H = (horizontal_bars_height + 1) / 2;
-
-    let bottom_bar_height: clip::H = horizontal_bars_height / 2;
-
-    let outer_clip_rect = clip::Rect {
-        x: left_bar_width..(
-            frame_buffer.width - right_bar_width
-        ),
-        y: top_bar_height..(
-            frame_buffer.height - bottom_bar_height
-        ),
-    };
-
+    pub fn mask(b: bool) -> i32 {
+        if b { -1 } else { 0 }
+    }
 
-    // This started as cached software rendering based on:
-    // https://rxi.github.io/cached_software_rendering.html
-    // But, a single cell turned out to be the most performant,
-    // in this particular case, so the code was simplifed with
-    // that in mind. Maybe it's faster to have a single cell, just
-    // because the rendered size is so small. Not sure.
+    /// SAFETY: The pointers produced by the code generated by these macros must
+    /// be valid to read 128 bytes from.
+    /// We omit unsafe in the macro's code in order to encourage the user to notice
+    /// the invariants they are expected to uphold.
+    macro_rules! _load {
+        (
+            $addr: expr $(,)?
+        ) => ({
+            let addr: *const _ = $addr;
+            core::ptr::read_unaligned(
+                addr as *const $crate::wide_scalar::I
+            )
+        });
+        (
+            $addr: expr,
+            $index: expr $(,)?
+        ) => ({
+            let addr: *const _ = $addr;
+            core::ptr::read_unaligned(
+                addr.add($index) as *const $crate::wide_scalar::I
+            )
+        });
+    }
+    pub(crate) use _load as load;
+
+    /// SAFETY: The pointers produced by the code generated by these macros must
+    /// be valid to write 128 bytes to.
+    /// We omit unsafe in the macro's code in order to encourage the user to notice
+    /// the invariants they are expected to uphold.
+    macro_rules! _store {
+        (
+            $vector: expr,
+            $addr: expr $(,)?
+        ) => ({
+            let vector: $crate::wide_scalar::I = $vector;
+            let addr: *mut _ = $addr;
+            core::ptr::write_unaligned(
+                addr as *mut $crate::wide_scalar::I,
+                vector,
+            )
+        });
+        (
+            $vector: expr,
+            $addr: expr,
+            $index: expr $(,)?
+        ) => ({
+            let vector: $crate::wide_scalar::I = $vector;
+            let addr: *mut _ = $addr;
+            core::ptr::write_unaligned(
+                addr.add($index) as *mut $crate::wide_scalar::I,
+                vector,
+            )
+        });
+    }
+    pub(crate) use _store as store;
+
+    macro_rules! _pick_via_mask {
+        (
+            $zero_case: expr,
+            $one_case: expr,
+            $mask: expr $(,)?
+        ) => ({
+            let zero_case: $crate::wide_scalar::I = $zero_case;
+            let one_case: $crate::wide_scalar::I = $one_case;
+            let mask: $crate::wide_scalar::I = $mask;
+            $crate::wide_scalar::zip_i(
+                $crate::wide_scalar::zip_i(one_case, mask, |o, m| o & m),
+                $crate::wide_scalar::zip_i(zero_case, mask, |z, m| z & !m),
+                |a, b| a | b,
+            )
+        });
+    }
+    pub(crate) use _pick_via_mask as pick_via_mask;
+
+    macro_rules! _i32x4 {
+        (
+            $e0: expr,
+            $e1: expr,
+            $e2: expr,
+            $e3: expr $(,)?
+        ) => ({
+            let v: $crate::wide_scalar::I = [$e0, $e1, $e2, $e3];
+            v
+        });
+    }
+    pub(crate) use _i32x4 as i32x4;
+
+    macro_rules! _and {
+        (
+            $a: expr,
+            $b: expr $(,)?
+        ) => ({
+            $crate::wide_scalar::zip_i($a, $b, |a, b| a & b)
+        });
+    }
+    pub(crate) use _and as and;
+
+    macro_rules! _and_not {
+        (
+            $just_anded: expr,
+            $notted: expr $(,)?
+        ) => ({
+            $crate::wide_scalar::zip_i($just_anded, $notted, |a, b| a & !b)
+        });
+    }
+    pub(crate) use _and_not as and_not;
+
+    macro_rules! _or {
+        (
+            $a: expr,
+            $b: expr $(,)?
+        ) => ({
+            $crate::wide_scalar::zip_i($a, $b, |a, b| a | b)
+        });
+    }
+    pub(crate) use _or as or;
+
+    macro_rules! _left_shift_32 {
+        (
+            $a: expr,
+            $imm8: expr $(,)?
+        ) => ({
+            $crate::wide_scalar::map_i($a, |a| ((a as u32) << $imm8) as i32)
+        });
+    }
+    pub(crate) use _left_shift_32 as left_shift_32;
+
+    macro_rules! _right_shift_32 {
+        (
+            $a: expr,
+            $imm8: expr $(,)?
+        ) => ({
+            $crate::wide_scalar::map_i($a, |a| ((a as u32) >> $imm8) as i32)
+        });
+    }
+    pub(crate) use _right_shift_32 as right_shift_32;
+
+    macro_rules! _f32_to_u32 {
+        (
+            $a: expr $(,)?
+        ) => ({
+            let a: $crate::wide_scalar::F = $a;
+            // Matches the round to nearest even that `_mm_cvtps_epi32` does
+            // by default.
+            [
+                a[0].round_ties_even() as i32,
+                a[1].round_ties_even() as i32,
+                a[2].round_ties_even() as i32,
+                a[3].round_ties_even() as i32,
+            ]
+        });
+    }
+    pub(crate) use _f32_to_u32 as f32_to_u32;
+
+    macro_rules! _u32_to_f32 {
+        (
+            $a: expr $(,)?
+        ) => ({
+            let a: $crate::wide_scalar::I = $a;
+            [
+                a[0] as f32,
+                a[1] as f32,
+                a[2] as f32,
+                a[3] as f32,
+            ]
+        });
+    }
+    pub(crate) use _u32_to_f32 as u32_to_f32;
+
+    macro_rules! _i32 {
+        (
+            $a: expr $(,)?
+        ) => ({
+            let a: i32 = $a;
+            let v: $crate::wide_scalar::I = [a; 4];
+            v
+        });
+    }
+    pub(crate) use _i32 as i32;
+
+    macro_rules! _f32 {
+        (
+            $a: expr $(,)?
+        ) => ({
+            let a: f32 = $a;
+            let v: $crate::wide_scalar::F = [a; 4];
+            v
+        });
+    }
+    pub(crate) use _f32 as f32;
+
+    macro_rules! _add_f32 {
+        (
+            $a: expr,
+            $b: expr $(,)?
+        ) => ({
+            $crate::wide_scalar::zip_f($a, $b, |a, b| a + b)
+        });
+    }
+    pub(crate) use _add_f32 as add_f32;
+
+    macro_rules! _add_i32 {
+        (
+            $a: expr,
+            $b: expr $(,)?
+        ) => ({
+            $crate::wide_scalar::zip_i($a, $b, |a, b| a.wrapping_add(b))
+        });
+    }
+    pub(crate) use _add_i32 as add_i32;
+
+    macro_rules! _sub {
+        (
+            $a: expr,
+            $b: expr $(,)?
+        ) => ({
+            $crate::wide_scalar::zip_f($a, $b, |a, b| a - b)
+        });
+    }
+    pub(crate) use _sub as sub;
+
+    macro_rules! _mul {
+        (
+            $a: expr,
+            $b: expr $(,)?
+        ) => ({
+            $crate::wide_scalar::zip_f($a, $b, |a, b| a * b)
+        });
+    }
+    pub(crate) use _mul as mul;
+
+    macro_rules! _recip {
+        (
+            $a: expr $(,)?
+        ) => ({
+            $crate::wide_scalar::map_f($a, |a| 1. / a)
+        });
+    }
+    pub(crate) use _recip as recip;
+
+    macro_rules! _sqrt {
+        (
+            $a: expr $(,)?
+        ) => ({
+            $crate::wide_scalar::map_f($a, |a| a.sqrt())
+        });
+    }
+    pub(crate) use _sqrt as sqrt;
+
+    macro_rules! _lt_mask_32 {
+        (
+            $lhs: expr,
+            $rhs: expr $(,)?
+        ) => ({
+            $crate::wide_scalar::zip_i(
+                $lhs,
+                $rhs,
+                |a, b| $crate::wide_scalar::mask(a < b)
+            )
+        });
+    }
+    pub(crate) use _lt_mask_32 as lt_mask_32;
+
+    macro_rules! _eq_mask_u32 {
+        (
+            $lhs: expr,
+            $rhs: expr $(,)?
+        ) => ({
+            $crate::wide_scalar::zip_i(
+                $lhs,
+                $rhs,
+                |a, b| $crate::wide_scalar::mask(a == b)
+            )
+        });
+    }
+    pub(crate) use _eq_mask_u32 as eq_mask_u32;
+
+    macro_rules! _gt_mask_32 {
+        (
+            $lhs: expr,
+            $rhs: expr $(,)?
+        ) => ({
+            $crate::wide_scalar::zip_i(
+                $lhs,
+                $rhs,
+                |a, b| $crate::wide_scalar::mask(a > b)
+            )
+        });
+    }
+    pub(crate) use _gt_mask_32 as gt_mask_32;
+
+    macro_rules! _reverse_32 {
+        (
+            $a: expr $(,)?
+        ) => ({
+            let a: $crate::wide_scalar::I = $a;
+            [a[3], a[2], a[1], a[0]]
+        });
+    }
+    pub(crate) use _reverse_32 as reverse_32;
+}
+
+#[cfg(not(any(
+    all(
+        target_arch = "x86_64",
+        target_feature = "sse",
+        target_feature = "sse2",
+    ),
+    all(
+        target_arch = "wasm32",
+        target_feature = "simd128",
+    ),
+)))]
+use wide_scalar as wide;
+
+#[cfg(test)]
+macro_rules! to_array {
+    ($wide: ident, $v: expr) => ({
+        let mut arr = [0; $wide::WIDTH as usize];
+        let v = $v;
+
+        // SAFETY: The pointers produced by the code generated by
+        // these macros are valid to read 128 bytes from.
+        unsafe {
+            $wide::store!(v, &mut arr);
+        }
+
+        arr
+    })
+}
+
+/// Defines the same tests for each `wide` implementation, so we can tell they
+/// all behave the same way.
+#[cfg(test)]
+macro_rules! wide_tests {
+    ($mod_name: ident, $wide: ident) => {
+        mod $mod_name {
+            use super::$wide::{self, *};
+
+            /// These names chosen to be the same length as `0`.
+            const A: i32 = 0xAAAA_AAAAu32 as i32;
+            const B: i32 = 0xBBBB_BBBBu32 as i32;
+            const C: i32 = 0xCCCC_CCCCu32 as i32;
+            const D: i32 = 0xDDDD_DDDDu32 as i32;
+            const F: i32 = 0xFFFF_FFFFu32 as i32;
+
+            #[test]
+            fn pick_via_mask_returns_the_expected_result_in_this_or_like_case() {
+                let left = i32x4!(A, B, 0, 0);
+                let right = i32x4!(0, 0, C, D);
+                let mask = i32x4!(0, 0, F, F);
+
+                let actual = pick_via_mask!(
+                    left,
+                    right,
+                    mask,
+                );
+
+                assert_eq!(
+                    to_array!($wide, actual),
+                    to_array!($wide, i32x4!(A, B, C, D))
+                );
+            }
+
+            #[test]
+            fn reverse_32_returns_the_expected_result_in_this_case() {
+                let actual = reverse_32!(i32x4!(A, B, C, D));
+
+                assert_eq!(
+                    to_array!($wide, actual),
+                    to_array!($wide, i32x4!(D, C, B, A))
+                );
+            }
+
+            #[test]
+            fn load_returns_what_was_stored() {
+                let arr = [A, B, C, D, F];
+
+                // SAFETY: The pointers produced by the code generated by
+                // these macros are valid to read 128 bytes from.
+                let actual = unsafe { load!(arr.as_ptr(), 1) };
+
+                assert_eq!(to_array!($wide, actual), [B, C, D, F]);
+            }
+
+            #[test]
+            fn the_bitwise_operations_return_the_expected_results_in_these_cases() {
+                let a = i32x4!(A, A, 0, F);
+                let b = i32x4!(F, 0, B, C);
+
+                assert_eq!(to_array!($wide, and!(a, b)), [A, 0, 0, C]);
+                assert_eq!(to_array!($wide, or!(a, b)), [F, A, B, F]);
+                assert_eq!(to_array!($wide, and_not!(a, b)), [0, A, 0, !C]);
+            }
+
+            #[test]
+            fn the_shifts_do_not_extend_the_sign_bit() {
+                let a = i32x4!(1, F, A, 0x0F);
+
+                assert_eq!(
+                    to_array!($wide, left_shift_32!(a, 4)),
+                    [0x10, 0xFFFF_FFF0u32 as i32, 0xAAAA_AAA0u32 as i32, 0xF0]
+                );
+                assert_eq!(
+                    to_array!($wide, right_shift_32!(a, 4)),
+                    [0, 0x0FFF_FFFF, 0x0AAA_AAAA, 0]
+                );
+            }
+
+            #[test]
+            fn the_masks_compare_as_signed_in_these_cases() {
+                let a = i32x4!(-1, 0, 1, 2);
+                let one = i32!(1);
+
+                assert_eq!(to_array!($wide, lt_mask_32!(a, one)), [F, F, 0, 0]);
+                assert_eq!(to_array!($wide, eq_mask_u32!(a, one)), [0, 0, F, 0]);
+                assert_eq!(to_array!($wide, gt_mask_32!(a, one)), [0, 0, 0, F]);
+            }
+
+            #[test]
+            fn add_i32_wraps() {
+                let actual = add_i32!(i32x4!(i32::MAX, 1, -1, 0), i32!(1));
+
+                assert_eq!(to_array!($wide, actual), [i32::MIN, 2, 0, 1]);
+            }
+
+            #[test]
+            fn f32_to_u32_rounds_ties_to_even() {
+                let halves = mul!(
+                    u32_to_f32!(i32x4!(1, 3, 5, 509)),
+                    f32!(0.5)
+                );
+
+                assert_eq!(to_array!($wide, f32_to_u32!(halves)), [0, 2, 2, 254]);
+            }
+
+            #[test]
+            fn the_float_arithmetic_returns_the_expected_results_in_these_cases() {
+                let a = u32_to_f32!(i32x4!(0, 1, 4, 65025));
+
+                assert_eq!(
+                    to_array!($wide, f32_to_u32!(sqrt!(a))),
+                    [0, 1, 2, 255]
+                );
+                assert_eq!(
+                    to_array!($wide, f32_to_u32!(add_f32!(a, f32!(1.)))),
+                    [1, 2, 5, 65026]
+                );
+                assert_eq!(
+                    to_array!($wide, f32_to_u32!(sub!(a, f32!(1.)))),
+                    [-1, 0, 3, 65024]
+                );
+                assert_eq!(
+                    to_array!($wide, f32_to_u32!(mul!(a, f32!(2.)))),
+                    [0, 2, 8, 130050]
+                );
+            }
+
+            #[test]
+            fn recip_is_close_to_the_reciprocal_in_these_cases() {
+                // Some implementations only approximate the reciprocal.
+                let actual = to_array!(
+                    $wide,
+                    f32_to_u32!(
+                        mul!(
+                            recip!(u32_to_f32!(i32x4!(1, 2, 4, 8))),
+                            f32!(1000.)
+                        )
+                    )
+                );
+
+                let expected: [i32; 4] = [1000, 500, 250, 125];
+
+                for (a, e) in actual.into_iter().zip(expected) {
+                    assert!((a - e).abs() <= 1, "{actual:?} vs {expected:?}");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+wide_tests!(wide_tests, wide);
+
+#[cfg(test)]
+wide_tests!(wide_scalar_tests, wide_scalar);
+
+pub fn render(
+    frame_buffer: &mut FrameBuffer,
+    commands: &[Command],
+) -> NeedsRedraw {
+    // This started as cached software rendering based on:
+    // https://rxi.github.io/cached_software_rendering.html
+    // For a while it used a single cell covering the whole frame, which
+    // worked out fine while most frames were identical, but with a moving
+    // camera, nearly every frame changes. So now we hash the commands
+    // into a grid of tiles, and only redraw the tiles that changed.
 
     frame_buffer.cells.reset_then_hash_commands(
         commands,
         (frame_buffer.width, frame_buffer.height),
     );
 
-    let expected_length = usize::from(frame_buffer.width)
-    * usize::from(frame_buffer.height);
+    let size = (frame_buffer.width, frame_buffer.height);
+    let viewport_changed = frame_buffer.previous_size != size
+    || frame_buffer.previous_scale_mode != frame_buffer.scale_mode;
+
+    // The hashes only cover the commands, so they don't notice when the
+    // pixels of a sheet change.
+    let redraw_all = viewport_changed || frame_buffer.sheets_changed;
+
+    #[cfg(feature = "post-process")]
+    let redraw_all = redraw_all
+    || frame_buffer.previous_post_process != frame_buffer.post_process;
+
+    frame_buffer.sheets_changed = false;
+
+    frame_buffer.dirty.clear();
+    frame_buffer.damage.clear();
+
+    if viewport_changed {
+        frame_buffer.previous_size = size;
+        frame_buffer.previous_scale_mode = frame_buffer.scale_mode;
+        frame_buffer.viewport = Viewport::new(
+            frame_buffer.scale_mode,
+            size,
+            frame_buffer.logical_size,
+        );
+
+        let expected_length = usize::from(frame_buffer.width)
+        * usize::from(frame_buffer.height);
+
+        // Clear the whole buffer, since the bars may have moved.
+        frame_buffer.buffer.clear();
+        frame_buffer.buffer.resize(expected_length, 0);
+    }
+
+    #[cfg(feature = "post-process")]
+    {
+        frame_buffer.previous_post_process = frame_buffer.post_process;
+    }
+
+    if redraw_all {
+        frame_buffer.dirty.push(clip::Rect {
+            x: 0..frame_buffer.logical_size.width() as clip::X,
+            y: 0..frame_buffer.logical_size.height() as clip::Y,
+        });
+    } else {
+        frame_buffer.cells.push_dirty_rects(
+            frame_buffer.damage_mode,
+            &mut frame_buffer.dirty,
+        );
+    }
+
+    if frame_buffer.dirty.is_empty() {
+        frame_buffer.cells.swap();
+        return NeedsRedraw::No;
+    }
+
+    let logical_size = frame_buffer.logical_size;
+
+    for dirty in frame_buffer.dirty.iter() {
+        for y in dirty.y.clone() {
+            let row_start = usize::from(y) * logical_size.width() as usize;
+
+            frame_buffer.unscaled_buffer[
+                row_start + usize::from(dirty.x.start)
+                ..row_start + usize::from(dirty.x.end)
+            ].fill(colours::BLACK);
+        }
+
+        for command in commands.iter() {
+            draw_command(
+                &mut frame_buffer.unscaled_buffer,
+                logical_size,
+                &frame_buffer.sheet_overrides,
+                command,
+                dirty,
+            );
+        }
+
+        #[cfg(feature = "post-process")]
+        post::dither(
+            &mut frame_buffer.unscaled_buffer,
+            logical_size,
+            frame_buffer.post_process.dither_levels,
+            dirty,
+        );
+    }
+
+    let viewport = &frame_buffer.viewport;
+
+    for dirty in frame_buffer.dirty.iter() {
+        let scaled = match &viewport.scaling {
+            &Scaling::Integer(multiplier) => upscale(
+                &mut frame_buffer.buffer,
+                frame_buffer.width,
+                &frame_buffer.unscaled_buffer,
+                logical_size,
+                multiplier,
+                (viewport.rect.x.start, viewport.rect.y.start),
+                dirty,
+            ),
+            Scaling::Filtered { x, y } => scale_filtered(
+                &mut frame_buffer.buffer,
+                frame_buffer.width,
+                &frame_buffer.unscaled_buffer,
+                logical_size,
+                (x, y),
+                (viewport.rect.x.start, viewport.rect.y.start),
+                dirty,
+            ),
+        };
+
+        // This needs to happen before the next dirty rect is scaled, in case
+        // the scaled rects overlap, so no pixel gets processed twice.
+        #[cfg(feature = "post-process")]
+        post::apply(
+            &mut frame_buffer.buffer,
+            frame_buffer.width,
+            &frame_buffer.post_process,
+            logical_size,
+            &viewport.rect,
+            &scaled,
+        );
+
+        frame_buffer.damage.push(scaled);
+    }
+
+    if redraw_all {
+        frame_buffer.damage.clear();
+        frame_buffer.damage.push(clip::Rect {
+            x: 0..frame_buffer.width,
+            y: 0..frame_buffer.height,
+        });
+    }
+
+    frame_buffer.cells.swap();
+
+    NeedsRedraw::Yes
+}
+
+/// Copies the `dirty` part of `unscaled_buffer` to `buffer`, scaled up by
+/// `multiplier`, then returns the part of `buffer` that was written to.
+fn upscale(
+    buffer: &mut [ARGB],
+    width: clip::W,
+    unscaled_buffer: &[ARGB],
+    logical_size: command::Size,
+    multiplier: u16,
+    (left_bar_width, top_bar_height): (clip::W, clip::H),
+    dirty: &clip::Rect,
+) -> clip::Rect {
+    let multiplier = usize::from(multiplier);
+    let width = usize::from(width);
+    let left_bar_width = usize::from(left_bar_width);
+    let top_bar_height = usize::from(top_bar_height);
+
+    let x_start = usize::from(dirty.x.start);
+    let x_end = usize::from(dirty.x.end);
+    let d_row_len = (x_end - x_start) * multiplier;
+
+    for y in dirty.y.clone() {
+        let src_row_start = usize::from(y) * logical_size.width() as usize;
+
+        let d_row_start = (top_bar_height + usize::from(y) * multiplier) * width
+            + left_bar_width
+            + x_start * multiplier;
+
+        let Some(d_row) = buffer.get_mut(d_row_start..d_row_start + d_row_len) else {
+            continue
+        };
+
+        upscale_row(
+            d_row,
+            &unscaled_buffer[src_row_start + x_start..src_row_start + x_end],
+            multiplier,
+        );
+
+        // The rest of the rows for this source row are identical to the
+        // first one, so we can just copy it.
+        for y_repeat in 1..multiplier {
+            let d_i = d_row_start + y_repeat * width;
+            if d_i + d_row_len > buffer.len() {
+                break
+            }
+
+            buffer.copy_within(d_row_start..d_row_start + d_row_len, d_i);
+        }
+    }
+
+    clip::Rect {
+        x: (left_bar_width + x_start * multiplier) as clip::X
+            ..(left_bar_width + x_end * multiplier) as clip::X,
+        y: (top_bar_height + usize::from(dirty.y.start) * multiplier) as clip::Y
+            ..(top_bar_height + usize::from(dirty.y.end) * multiplier) as clip::Y,
+    }
+}
+
+/// Writes the output pixels made from the `dirty` part of `unscaled_buffer`
+/// to `buffer`, blending the unscaled pixels as `x_axis` and `y_axis` say to,
+/// then returns the part of `buffer` that was written to.
+fn scale_filtered(
+    buffer: &mut [ARGB],
+    width: clip::W,
+    unscaled_buffer: &[ARGB],
+    logical_size: command::Size,
+    (x_axis, y_axis): (&Axis, &Axis),
+    (left_bar_width, top_bar_height): (clip::W, clip::H),
+    dirty: &clip::Rect,
+) -> clip::Rect {
+    // gamma to linear and back, so the blends look even.
+    fn linear(channel: u32) -> f32 {
+        let channel = (channel & 255) as f32 * (1./255.);
+        channel * channel
+    }
+
+    fn gamma(channel: f32) -> u32 {
+        (channel.sqrt() * 255.).round().clamp(0., 255.) as u32
+    }
+
+    let width = usize::from(width);
+    let left_bar_width = usize::from(left_bar_width);
+    let top_bar_height = usize::from(top_bar_height);
+
+    let logical_width = logical_size.width() as usize;
+    let last_x = logical_width - 1;
+    let last_y = logical_size.height() as usize - 1;
+
+    let xs = x_axis.range_touching(&dirty.x);
+    let ys = y_axis.range_touching(&dirty.y);
+
+    for d_y in ys.clone() {
+        let d_row_start = (top_bar_height + d_y) * width + left_bar_width;
+
+        let y_start = usize::from(y_axis.starts[d_y]);
+        let y_weights = &y_axis.weights[d_y * y_axis.taps..(d_y + 1) * y_axis.taps];
+
+        for d_x in xs.clone() {
+            let x_start = usize::from(x_axis.starts[d_x]);
+            let x_weights = &x_axis.weights[d_x * x_axis.taps..(d_x + 1) * x_axis.taps];
+
+            let (mut a, mut r, mut g, mut b) = (0., 0., 0., 0.);
+
+            for (y_tap, &y_weight) in y_weights.iter().enumerate() {
+                let src_row_start = core::cmp::min(y_start + y_tap, last_y)
+                    * logical_width;
+
+                for (x_tap, &x_weight) in x_weights.iter().enumerate() {
+                    let weight = y_weight * x_weight;
+                    if weight == 0. {
+                        continue
+                    }
+
+                    let pixel = unscaled_buffer[
+                        src_row_start + core::cmp::min(x_start + x_tap, last_x)
+                    ];
+
+                    a += weight * linear(pixel >> 24);
+                    r += weight * linear(pixel >> 16);
+                    g += weight * linear(pixel >> 8);
+                    b += weight * linear(pixel);
+                }
+            }
+
+            if let Some(d_pixel) = buffer.get_mut(d_row_start + d_x) {
+                *d_pixel = gamma(a) << 24
+                    | gamma(r) << 16
+                    | gamma(g) << 8
+                    | gamma(b);
+            }
+        }
+    }
+
+    clip::Rect {
+        x: (left_bar_width + xs.start) as clip::X
+            ..(left_bar_width + xs.end) as clip::X,
+        y: (top_bar_height + ys.start) as clip::Y
+            ..(top_bar_height + ys.end) as clip::Y,
+    }
+}
+
+macro_rules! define_upscale_row {
+    ($name: ident, $wide: ident) => {
+        /// Writes each pixel in `src` to `multiplier` adjacent pixels in `dest`.
+        fn $name(dest: &mut [ARGB], src: &[ARGB], multiplier: usize) {
+            debug_assert_eq!(dest.len(), src.len() * multiplier);
+
+            if multiplier == 1 {
+                dest.copy_from_slice(src);
+                return
+            }
+
+            let lanes = $wide::WIDTH as usize;
+
+            // Each store of a splatted pixel can write past the pixels for
+            // that pixel, but the next store overwrites those extra pixels.
+            // So we only need to be careful near the end of `dest`.
+            let last_wide_i = dest.len().saturating_sub(lanes);
+
+            let mut d_i = 0;
+            for &colour in src {
+                let splatted = $wide::i32!(colour as i32);
+
+                let next_d_i = d_i + multiplier;
+                while d_i < next_d_i {
+                    if d_i <= last_wide_i {
+                        // SAFETY: `d_i + lanes <= dest.len()` so the pointer
+                        // produced by this macro is valid to write 128 bytes to.
+                        unsafe {
+                            $wide::store!(
+                                splatted,
+                                dest.as_mut_ptr(),
+                                d_i,
+                            );
+                        }
+                        d_i += lanes;
+                    } else {
+                        dest[d_i..next_d_i].fill(colour);
+                        d_i = next_d_i;
+                    }
+                }
+                d_i = next_d_i;
+            }
+        }
+    }
+}
+
+define_upscale_row!(upscale_row, wide);
+
+#[cfg(test)]
+define_upscale_row!(upscale_row_scalar, wide_scalar);
+
+#[test]
+fn upscale_row_matches_the_scalar_version_on_these_examples() {
+    // A small LCG, so the pixels aren't all the same.
+    let mut seed: u32 = 0x1234_5678;
+    let mut next = || {
+        seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        seed
+    };
+
+    for multiplier in 1..=9 {
+        for len in [0, 1, 2, 3, 4, 5, 8, 17, 480] {
+            let src: Vec<ARGB> = (0..len).map(|_| next()).collect();
+
+            let mut expected = vec![0; len * multiplier];
+            upscale_row_scalar(&mut expected, &src, multiplier);
+
+            let mut actual = vec![0; len * multiplier];
+            upscale_row(&mut actual, &src, multiplier);
+
+            assert_eq!(actual, expected, "{multiplier} {len}");
+
+            for (i, &colour) in src.iter().enumerate() {
+                assert!(
+                    expected[i * multiplier..(i + 1) * multiplier]
+                        .iter()
+                        .all(|&c| c == colour),
+                    "{multiplier} {len} {i}"
+                );
+            }
+        }
+    }
+}
+
+/// Draws the part of the command that is inside `clip_rect`.
+fn draw_command(
+    unscaled_buffer: &mut [ARGB],
+    logical_size: command::Size,
+    sheet_overrides: &[Option<assets::OwnedSheet>; sprite::SheetId::COUNT],
+    &Command {
+        sheet,
+        sprite_xy: sprite::XY { x: sprite_x, y: sprite_y },
+        colour_override,
+        rect,
+        flip,
+        tint,
+        alpha,
+        palette,
+        kind,
+    }: &Command,
+    clip_rect: &clip::Rect,
+) {
+    if alpha == Alpha::TRANSPARENT {
+        return
+    }
+
+    let wide_0 = wide::i32!(0);
+    let wide_1_f32 = wide::f32!(1.);
+    let wide_255_i32 = wide::i32!(255);
+    let wide_inv_255_f32 = wide::f32!(1./255.);
+    let wide_255_f32 = wide::f32!(255.);
+    let wide_0_to_w = wide::i32x4!(0, 1, 2, 3);
+    let wide_opaque_white = wide::i32!(-1);
+
+    // gamma to linear, for the per-command values.
+    fn linear(channel: u32) -> f32 {
+        let channel = (channel & 255) as f32 * (1./255.);
+        channel * channel
+    }
+
+    let wide_alpha = wide::f32!(linear(alpha.0.into()));
+
+    let (wide_tint_r, wide_tint_g, wide_tint_b) = if tint == 0 {
+        (wide_1_f32, wide_1_f32, wide_1_f32)
+    } else {
+        (
+            wide::f32!(linear(tint >> 16)),
+            wide::f32!(linear(tint >> 8)),
+            wide::f32!(linear(tint)),
+        )
+    };
+
+    // `0` means no override, so that stays as it is.
+    let colour_override = if colour_override == 0 {
+        0
+    } else {
+        palette.recolour(colour_override, palette::nearest(colour_override))
+    };
+
+    let colour_override_value = wide::i32!(colour_override as i32);
+
+    let not_colour_override_mask = wide::eq_mask_u32!(
+        colour_override_value,
+        wide_0
+    );
+
+    let Rect {
+        x_min,
+        y_min,
+        x_max,
+        y_max,
+    } = rect;
+
+    // TODO make this wide too?
+    let x_min = i16::from(x_min);
+    let y_min = i16::from(y_min);
+    let x_max = i16::from(x_max);
+    let y_max = i16::from(y_max);
+
+    let sprite_x = usize::from(sprite_x);
+    let sprite_y = usize::from(sprite_y);
+
+    let x_min = x_min as clip::X;
+    let y_min = y_min as clip::Y;
+    let x_max = x_max as clip::W;
+    let y_max = y_max as clip::H;
+
+    let x_end = x_max + 1;
+    let y_end = y_max + 1;
+
+    let draw_x_min = core::cmp::max(x_min, clip_rect.x.start);
+    let draw_y_min = core::cmp::max(y_min, clip_rect.y.start);
+    let draw_x_end = core::cmp::min(x_end, clip_rect.x.end);
+    let draw_y_end = core::cmp::min(y_end, clip_rect.y.end);
+
+    if draw_x_min >= draw_x_end || draw_y_min >= draw_y_end {
+        return
+    }
+
+    let wide_x_end = wide::i32!(draw_x_end.into());
+
+    let (src, src_palette_indices, src_w): (&[ARGB], &[PaletteIndex], usize) =
+        match &sheet_overrides[sheet as usize] {
+            Some(owned) => (&owned.pixels, &owned.palette_indices, owned.width),
+            None => {
+                let sheet = assets::sheet(sheet);
+                (sheet.pixels, sheet.palette_indices, sheet.width)
+            }
+        };
+
+    let flip_x = flip.contains(Flip::HORIZONTAL);
+    let flip_y = flip.contains(Flip::VERTICAL);
+    let last_x_offset = usize::from(x_max.saturating_sub(x_min));
+    let last_y_offset = usize::from(y_max.saturating_sub(y_min));
+
+    let y_iter_counts = usize::from(draw_y_min - y_min)..;
+    for (y_iter_count, y) in y_iter_counts.zip(draw_y_min..draw_y_end) {
+        let src_y = sprite_y + if flip_y {
+            last_y_offset - y_iter_count
+        } else {
+            y_iter_count
+        };
+
+        let mut x_iter_count = usize::from(draw_x_min - x_min);
+        let mut x = draw_x_min;
+
+        while x < draw_x_end {
+            let wide_xs = wide::add_i32!(
+                wide::i32!(x.into()),
+                wide_0_to_w
+            );
+
+            let dest_index = usize::from(y)
+                * logical_size.width() as usize
+                + usize::from(x);
+
+            let unders = unsafe {
+                wide::load!(
+                    unscaled_buffer.as_ptr(),
+                    dest_index,
+                )
+            };
+
+            let src_x = sprite_x + if flip_x {
+                last_x_offset - x_iter_count
+            } else {
+                x_iter_count
+            };
+
+            // This can be past the end of `src`, if the sheet was replaced
+            // with a smaller one, so the loads below check for that.
+            let base_src_i = src_y * src_w + src_x;
+            let gfx_colours = if kind == command::Kind::Fill {
+                wide_opaque_white
+            } else if palette != palette::Id::Base {
+                // Each pixel is recoloured according to its own palette
+                // index, so get them one at a time.
+                let get = |offset: usize| {
+                    let i = if flip_x {
+                        base_src_i.checked_sub(offset)
+                    } else {
+                        Some(base_src_i + offset)
+                    };
+
+                    i.and_then(|i| Some(palette.recolour(
+                        *src.get(i)?,
+                        *src_palette_indices.get(i)?,
+                    )))
+                    .unwrap_or(0) as i32
+                };
+
+                wide::i32x4!(get(0), get(1), get(2), get(3))
+            } else if flip_x {
+                // The lanes go right to left across the sprite, so we load
+                // the pixels ending at `base_src_i`, then reverse them.
+                match base_src_i.checked_sub(wide::WIDTH as usize - 1)
+                    .filter(|_| base_src_i < src.len()) {
+                    Some(low_src_i) => wide::reverse_32!(
+                        unsafe {
+                            wide::load!(
+                                src.as_ptr(),
+                                low_src_i
+                            )
+                        }
+                    ),
+                    None => {
+                        // A wide load here would read from outside the
+                        // sheet, so get the pixels one at a time.
+                        let get = |offset: usize| {
+                            base_src_i.checked_sub(offset)
+                                .and_then(|i| src.get(i))
+                                .copied()
+                                .unwrap_or(0) as i32
+                        };
+
+                        wide::i32x4!(get(0), get(1), get(2), get(3))
+                    }
+                }
+            } else if base_src_i + wide::WIDTH as usize <= src.len() {
+                unsafe {
+                    wide::load!(
+                        src.as_ptr(),
+                        base_src_i
+                    )
+                }
+            } else {
+                // A wide load here would read from past the end of the
+                // sheet, so get the pixels one at a time.
+                let get = |offset: usize| {
+                    src.get(base_src_i + offset)
+                        .copied()
+                        .unwrap_or(0) as i32
+                };
+
+                wide::i32x4!(get(0), get(1), get(2), get(3))
+            };
+
+            let is_full_alpha_mask = wide::eq_mask_u32!(
+                wide::right_shift_32!(
+                    gfx_colours,
+                    24
+                ),
+                wide_255_i32
+            );
+
+            let do_override_mask = wide::and_not!(
+                is_full_alpha_mask,
+                not_colour_override_mask
+            );
+
+            let gfx_colours = wide::pick_via_mask!(
+                gfx_colours,
+                colour_override_value,
+                do_override_mask,
+            );
+
+            let should_write =
+                wide::lt_mask_32!(
+                    wide_xs,
+                    wide_x_end
+                );
+
+            // Don't need to mask the shifted in zeroes.
+            let gfx_colour_a = wide::right_shift_32!(
+                gfx_colours,
+                24
+            );
+
+            let gfx_colour_r = wide::and!(
+                wide::right_shift_32!(
+                    gfx_colours,
+                    16
+                ),
+                wide_255_i32
+            );
+
+            let gfx_colour_g = wide::and!(
+                wide::right_shift_32!(
+                    gfx_colours,
+                    8
+                ),
+                wide_255_i32
+            );
+
+            // Don't need to shift since it's already in the right spot
+            let gfx_colour_b = wide::and!(
+                gfx_colours,
+                wide_255_i32
+            );
+
+            // Don't need to mask the shifted in zeroes.
+            let under_a = wide::right_shift_32!(
+                unders,
+                24
+            );
+
+            let under_r = wide::and!(
+                wide::right_shift_32!(
+                    unders,
+                    16
+                ),
+                wide_255_i32
+            );
+
+            let under_g = wide::and!(
+                wide::right_shift_32!(
+                    unders,
+                    8
+                ),
+                wide_255_i32
+            );
+
+            // Don't need to shift since it's already in the right spot
+            let under_b = wide::and!(
+                unders,
+                wide_255_i32
+            );
+
+            // gamma to linear
+            let mut a_g = wide::mul!(
+                wide::u32_to_f32!(
+                    gfx_colour_a
+                ),
+                wide_inv_255_f32
+            );
+            a_g = wide::mul!(a_g, a_g);
+            let mut r_g = wide::mul!(
+                wide::u32_to_f32!(
+                    gfx_colour_r
+                ),
+                wide_inv_255_f32
+            );
+            r_g = wide::mul!(r_g, r_g);
+            let mut g_g = wide::mul!(
+                wide::u32_to_f32!(
+                    gfx_colour_g
+                ),
+                wide_inv_255_f32
+            );
+            g_g = wide::mul!(g_g, g_g);
+            let mut b_g = wide::mul!(
+                wide::u32_to_f32!(
+                    gfx_colour_b
+                ),
+                wide_inv_255_f32
+            );
+            b_g = wide::mul!(b_g, b_g);
+
+            a_g = wide::mul!(a_g, wide_alpha);
+            r_g = wide::mul!(r_g, wide_tint_r);
+            g_g = wide::mul!(g_g, wide_tint_g);
+            b_g = wide::mul!(b_g, wide_tint_b);
+
+            let mut a_u = wide::mul!(
+                wide::u32_to_f32!(
+                    under_a
+                ),
+                wide_inv_255_f32
+            );
+            a_u = wide::mul!(a_u, a_u);
+            let mut r_u = wide::mul!(
+                wide::u32_to_f32!(
+                    under_r
+                ),
+                wide_inv_255_f32
+            );
+            r_u = wide::mul!(r_u, r_u);
+            let mut g_u = wide::mul!(
+                wide::u32_to_f32!(
+                    under_g
+                ),
+                wide_inv_255_f32
+            );
+            g_u = wide::mul!(g_u, g_u);
+            let mut b_u = wide::mul!(
+                wide::u32_to_f32!(
+                    under_b
+                ),
+                wide_inv_255_f32
+            );
+            b_u = wide::mul!(b_u, b_u);
+
+            // perform alpha blending
+            let o_a = wide::add_f32!(
+                a_g,
+                wide::mul!(
+                    a_u,
+                    wide::sub!(wide_1_f32, a_g)
+                )
+            );
+
+            let inv_o_a = wide::recip!(o_a);
+
+            let one_minus_a_g = wide::sub!(wide_1_f32, a_g);
+
+            let o_r = wide::mul!(
+                wide::add_f32!(
+                    wide::mul!(r_g, a_g),
+                    wide::mul!(
+                        r_u,
+                        one_minus_a_g
+                    )
+                ),
+                inv_o_a
+            );
+            let o_g = wide::mul!(
+                wide::add_f32!(
+                    wide::mul!(g_g, a_g),
+                    wide::mul!(
+                        g_u,
+                        one_minus_a_g
+                    )
+                ),
+                inv_o_a
+            );
+            let o_b = wide::mul!(
+                wide::add_f32!(
+                    wide::mul!(b_g, a_g),
+                    wide::mul!(
+                        b_u,
+                        one_minus_a_g
+                    )
+                ),
+                inv_o_a
+            );
+
+            // linear to gamma
+            let rendered_a = wide::f32_to_u32!(
+                wide::mul!(
+                    wide_255_f32,
+                    wide::sqrt!(o_a)
+                )
+            );
+            let rendered_r = wide::f32_to_u32!(
+                wide::mul!(
+                    wide_255_f32,
+                    wide::sqrt!(o_r)
+                )
+            );
+            let rendered_g = wide::f32_to_u32!(
+                wide::mul!(
+                    wide_255_f32,
+                    wide::sqrt!(o_g)
+                )
+            );
+            let rendered_b = wide::f32_to_u32!(
+                wide::mul!(
+                    wide_255_f32,
+                    wide::sqrt!(o_b)
+                )
+            );
+
+            let rendered = wide::or!(
+                wide::or!(
+                    wide::left_shift_32!(rendered_a, 24),
+                    wide::left_shift_32!(rendered_r, 16),
+                ),
+                wide::or!(
+                    wide::left_shift_32!(rendered_g, 8),
+                    rendered_b,
+                )
+            );
+
+            let to_store = wide::pick_via_mask!(
+                unders,
+                rendered,
+                should_write
+            );
+
+            // SAFETY: The pointers produced by the code generated by
+            // this macro is valid to write 128 bytes to.
+            unsafe {
+                wide::store!(
+                    to_store,
+                    unscaled_buffer.as_mut_ptr(),
+                    dest_index,
+                );
+            }
+
+            x_iter_count += wide::WIDTH as usize;
+            x += wide::WIDTH as u16;
+        }
+    }
+}
+
+#[test]
+fn render_does_not_panic_on_these_examples() {
+    let commands = [
+        Command {
+            rect: Rect {
+                x_min: command::X::clipped_inner(-166),
+                y_min: command::Y::clipped_inner(96),
+                x_max: command::X::clipped_inner(-56),
+                y_max: command::Y::clipped_inner(223),
+            },
+            sprite_xy: sprite::XY { x: sprite::X(128), y: sprite::Y(128) },
+            .. Command::default()
+        },
+    ];
+
+    let mut buffer = FrameBuffer::from_size((400, 300));
+
+    render(&mut buffer, &commands);
+}
+
+#[test]
+fn render_does_not_panic_on_these_flipped_examples() {
+    let mut commands = Vec::new();
+
+    for flip in [Flip::HORIZONTAL, Flip::VERTICAL, Flip::BOTH] {
+        for (x, y) in [(0, 0), (-3, -3), (470, 310)] {
+            if let Ok(command) = Command::clipped(
+                unscaled::Rect {
+                    x: unscaled::X(x),
+                    y: unscaled::Y(y),
+                    w: unscaled::W(13),
+                    h: unscaled::H(7),
+                },
+                sprite::XY::default(),
+                0,
+                flip,
+            ) {
+                commands.push(command);
+            }
+        }
+    }
+
+    let mut buffer = FrameBuffer::from_size((480, 320));
+
+    render(&mut buffer, &commands);
+}
+
+#[test]
+fn render_does_not_panic_when_drawing_from_the_end_of_each_sheet() {
+    let mut commands = Vec::new();
+
+    for sheet in sprite::SheetId::ALL {
+        let assets::Sheet { width, height, .. } = *assets::sheet(sheet);
+
+        for flip in [Flip::NONE, Flip::HORIZONTAL] {
+            if let Ok(mut command) = Command::clipped(
+                unscaled::Rect {
+                    x: unscaled::X(10),
+                    y: unscaled::Y(10),
+                    w: unscaled::W(2),
+                    h: unscaled::H(1),
+                },
+                sprite::XY {
+                    x: sprite::X(width as sprite::Inner - 2),
+                    y: sprite::Y(height as sprite::Inner - 1),
+                },
+                0,
+                flip,
+            ) {
+                command.sheet = sheet;
+                commands.push(command);
+            }
+        }
+    }
+
+    let mut buffer = FrameBuffer::from_size((480, 320));
+
+    render(&mut buffer, &commands);
+}
+
+#[test]
+fn drawing_with_a_palette_matches_drawing_the_recoloured_pixels() {
+    const OFF_PALETTE: ARGB = 0xFF12_3456;
+    let half_alpha = |colour: ARGB| colour & 0x80FF_FFFF;
+
+    let sheet = assets::OwnedSheet::from_pixels(
+        5,
+        1,
+        vec![
+            PALETTE[0],
+            half_alpha(PALETTE[1]),
+            OFF_PALETTE,
+            PALETTE[7],
+            0,
+        ],
+    );
+
+    let render_sheet = |
+        to_render: assets::OwnedSheet,
+        palette: palette::Id,
+        flip: Flip,
+    | {
+        let mut command = Command::clipped(
+            unscaled::Rect {
+                x: unscaled::X(1),
+                y: unscaled::Y(2),
+                w: unscaled::W(5),
+                h: unscaled::H(1),
+            },
+            sprite::XY::default(),
+            0,
+            flip,
+        ).unwrap_or_default();
+        command.palette = palette;
 
-    if frame_buffer.buffer.len() != expected_length {
-        frame_buffer.buffer.clear();
-        // Hopefully this compiles to something not inefficent
-        frame_buffer.buffer.reserve(expected_length);
-        for _ in 0..expected_length {
-            frame_buffer.buffer.push(0);
+        let mut buffer = FrameBuffer::from_size((480, 320));
+        buffer.set_sheet(sprite::SheetId::default(), to_render);
+        render(&mut buffer, &[command]);
+
+        buffer.buffer
+    };
+
+    for palette in palette::Id::ALL {
+        let recoloured = assets::OwnedSheet {
+            pixels: sheet.pixels.iter()
+                .zip(&sheet.palette_indices)
+                .map(|(&pixel, &index)| palette.recolour(pixel, index))
+                .collect(),
+            ..sheet.clone()
+        };
+
+        for flip in [Flip::NONE, Flip::HORIZONTAL] {
+            let expected = render_sheet(
+                recoloured.clone(),
+                palette::Id::Base,
+                flip,
+            );
+            let actual = render_sheet(sheet.clone(), palette, flip);
+
+            assert!(expected == actual, "{palette:?} {flip:?}");
         }
     }
 
-    let (cells, cells_prev) = frame_buffer.cells.current_and_prev();
+    assert_ne!(
+        render_sheet(sheet.clone(), palette::Id::Base, Flip::NONE),
+        render_sheet(sheet, palette::Id::Night, Flip::NONE),
+    );
+}
 
-    if cells == cells_prev {
-        frame_buffer.cells.swap();
-        return NeedsRedraw::No;
-    }
+#[test]
+fn palettes_change_how_the_real_art_and_text_look() {
+    use platform_types::sprites;
 
-    let wide_0 = wide::i32!(0);
-    let wide_1_f32 = wide::f32!(1.);
-    let wide_255_i32 = wide::i32!(255);
-    let wide_inv_255_f32 = wide::f32!(1./255.);
-    let wide_255_f32 = wide::f32!(255.);
-    let wide_0_to_w = wide::i32x4!(0, 1, 2, 3);
+    let render_with = |palette: palette::Id| {
+        let mut commands = Vec::new();
+
+        let mut cube = Command::clipped(
+            unscaled::Rect {
+                x: unscaled::X(0),
+                y: unscaled::Y(0),
+                w: sprites::cube::WIDTH,
+                h: sprites::cube::HEIGHT,
+            },
+            sprites::cube::XY,
+            0,
+            Flip::NONE,
+        ).unwrap_or_default();
+        cube.sheet = sprites::cube::SHEET;
+        cube.palette = palette;
+        commands.push(cube);
+
+        // Text is drawn with `colour_override`.
+        let mut character = Command::clipped(
+            unscaled::Rect {
+                x: unscaled::X(200),
+                y: unscaled::Y(0),
+                w: unscaled::W(8),
+                h: unscaled::H(8),
+            },
+            sprites::font::FRAMES[usize::from(b'A')],
+            PALETTE[0],
+            Flip::NONE,
+        ).unwrap_or_default();
+        character.sheet = sprites::font::SHEET;
+        character.palette = palette;
+        commands.push(character);
+
+        let mut buffer = FrameBuffer::from_size((480, 320));
+        render(&mut buffer, &commands);
+
+        buffer.buffer
+    };
+
+    let cube_pixels = |buffer: &[ARGB]| -> Vec<ARGB> {
+        let cube_w = sprites::cube::WIDTH.0 as usize;
+        let cube_h = sprites::cube::HEIGHT.0 as usize;
+
+        (0..cube_h)
+            .flat_map(|y| buffer[y * 480..y * 480 + cube_w].to_vec())
+            .collect()
+    };
+    let character_pixels = |buffer: &[ARGB]| -> Vec<ARGB> {
+        (0..8)
+            .flat_map(|y| buffer[y * 480 + 200..y * 480 + 208].to_vec())
+            .collect()
+    };
 
-    // Hopefully this compiles to something not inefficent
-    for i in 0..frame_buffer.unscaled_buffer.len() {
-        frame_buffer.unscaled_buffer[i] = colours::BLACK;
+    let base = render_with(palette::Id::Base);
+    for palette in [palette::Id::Night, palette::Id::Greyscale] {
+        let other = render_with(palette);
+
+        assert_ne!(cube_pixels(&base), cube_pixels(&other), "{palette:?}");
+        assert_ne!(
+            character_pixels(&base),
+            character_pixels(&other),
+            "{palette:?}"
+        );
     }
+}
 
-    for &Command {
-        sprite_xy: sprite::XY { x: sprite_x, y: sprite_y },
-        colour_override,
+#[test]
+fn fill_commands_cover_exactly_their_rect_with_their_colour() {
+    let colour = PALETTE[2];
+
+    let command = Command::filled(
+        unscaled::Rect {
+            x: unscaled::X(1),
+            y: unscaled::Y(2),
+            w: unscaled::W(5),
+            h: unscaled::H(3),
+        },
+        colour,
+    ).unwrap_or_default();
+
+    let mut buffer = FrameBuffer::from_size((480, 320));
+    render(&mut buffer, &[command]);
+
+    for y in 0..8 {
+        for x in 0..10 {
+            let inside = (1..6).contains(&x) && (2..5).contains(&y);
+            assert_eq!(
+                buffer.buffer[y * 480 + x] == colour,
+                inside,
+                "{:?}",
+                (x, y)
+            );
+        }
+    }
+}
+
+#[test]
+fn tinted_partly_transparent_sprites_blend_with_what_is_under_them() {
+    const BACKGROUND: ARGB = 0xFF20_4060;
+    const SPRITE: ARGB = 0xFFC0_8040;
+    const TINT: ARGB = 0xFF80_FF40;
+    const ALPHA: u8 = 128;
+
+    let rect = unscaled::Rect {
+        x: unscaled::X(1),
+        y: unscaled::Y(2),
+        w: unscaled::W(1),
+        h: unscaled::H(1),
+    };
+
+    let background = Command::filled(rect, BACKGROUND).unwrap_or_default();
+
+    let mut sprite = Command::clipped(
         rect,
-    } in commands.iter() {
-        let colour_override_value = wide::i32!(colour_override as i32);
+        sprite::XY::default(),
+        0,
+        Flip::NONE,
+    ).unwrap_or_default();
+    sprite.tint = TINT;
+    sprite.alpha = Alpha(ALPHA);
+
+    let mut buffer = FrameBuffer::from_size((480, 320));
+    buffer.set_sheet(
+        sprite::SheetId::default(),
+        assets::OwnedSheet::from_pixels(1, 1, vec![SPRITE]),
+    );
+    render(&mut buffer, &[background, sprite]);
+
+    // The same blending `draw_command` does, in linear space, one channel
+    // at a time.
+    let linear = |colour: ARGB, shift: u32| {
+        let channel = f64::from((colour >> shift) & 255) / 255.;
+        channel * channel
+    };
+    let a = linear(ALPHA.into(), 0);
+    let expected_channel = |shift: u32| {
+        let over = linear(SPRITE, shift) * linear(TINT, shift);
+        let under = linear(BACKGROUND, shift);
+        (255. * (over * a + under * (1. - a)).sqrt()).round() as i32
+    };
 
-        let not_colour_override_mask = wide::eq_mask_u32!(
-            colour_override_value,
-            wide_0
+    let actual = buffer.buffer[2 * 480 + 1];
+    assert_eq!(actual >> 24, 255, "{actual:08X}");
+    for shift in [16, 8, 0] {
+        let actual_channel = ((actual >> shift) & 255) as i32;
+        // `wide::recip` is only an approximation.
+        assert!(
+            (actual_channel - expected_channel(shift)).abs() <= 1,
+            "{actual:08X} {shift} {}",
+            expected_channel(shift)
         );
+    }
 
-        let Rect {
-            x_min,
-            y_min,
-            x_max,
-            y_max,
-        } = rect;
-
-        // TODO make this wide too?
-        let x_min = i16::from(x_min);
-        let y_min = i16::from(y_min);
-        let x_max = i16::from(x_max);
-        let y_max = i16::from(y_max);
-
-        let sprite_x = usize::from(sprite_x);
-        let sprite_y = usize::from(sprite_y);
-
-        let x_min = x_min as clip::X;
-        let y_min = y_min as clip::Y;
-        let x_max = x_max as clip::W;
-        let y_max = y_max as clip::H;
-
-        let x_end = x_max + 1;
-        let y_end = y_max + 1;
-        let wide_x_end = wide::i32!(x_end.into());
-
-        let src_w = GFX_WIDTH as usize;
-
-        let mut y_iter_count = 0;
-        for y in y_min..y_end {
-            let mut x_iter_count = 0;
-            let mut x = x_min;
-
-            while x < x_end {
-                let wide_xs = wide::add_i32!(
-                    wide::i32!(x.into()),
-                    wide_0_to_w
-                );
+    // Make sure the tint and alpha did something.
+    assert_ne!(actual, SPRITE);
+    assert_ne!(actual, BACKGROUND);
+}
 
-                let dest_index = usize::from(y)
-                    * usize::from(command::WIDTH as u16)
-                    + usize::from(x);
+#[test]
+fn setting_a_smaller_sheet_redraws_everything_and_does_not_panic() {
+    let mut commands = Vec::new();
 
-                let unders = unsafe {
-                    wide::load!(
-                        frame_buffer.unscaled_buffer.as_ptr(),
-                        dest_index,
-                    )
-                };
+    for flip in [Flip::NONE, Flip::HORIZONTAL, Flip::VERTICAL] {
+        if let Ok(command) = Command::clipped(
+            unscaled::Rect {
+                x: unscaled::X(10),
+                y: unscaled::Y(10),
+                w: unscaled::W(16),
+                h: unscaled::H(16),
+            },
+            sprite::XY {
+                x: sprite::X(64),
+                y: sprite::Y(64),
+            },
+            0,
+            flip,
+        ) {
+            commands.push(command);
+        }
+    }
 
-                let base_src_i =
-                    (sprite_y + y_iter_count) * src_w
-                    + (sprite_x + x_iter_count);
-                debug_assert!(
-                    base_src_i < GFX.len(),
-                    "({sprite_y} + {y_iter_count}) * {src_w} + ({sprite_x} + {x_iter_count})
-{base_src_i} >= {}
-({x_min} to {x_end}, {y_min} to {y_end}) {rect:?}",
-                    GFX.len()
-                );
-                let gfx_colours = unsafe {
-                    wide::load!(
-                        GFX.as_ptr(),
-                        base_src_i
-                    )
-                };
+    let mut buffer = FrameBuffer::from_size((480, 320));
 
-                let is_full_alpha_mask = wide::eq_mask_u32!(
-                    wide::right_shift_32!(
-                        gfx_colours,
-                        24
-                    ),
-                    wide_255_i32
-                );
+    assert_eq!(render(&mut buffer, &commands), NeedsRedraw::Yes);
+    assert_eq!(render(&mut buffer, &commands), NeedsRedraw::No);
 
-                let do_override_mask = wide::and_not!(
-                    is_full_alpha_mask,
-                    not_colour_override_mask
-                );
+    buffer.set_sheet(
+        sprite::SheetId::default(),
+        assets::OwnedSheet::from_pixels(2, 2, vec![colours::WHITE; 4]),
+    );
 
-                let gfx_colours = wide::pick_via_mask!(
-                    gfx_colours,
-                    colour_override_value,
-                    do_override_mask,
-                );
+    assert_eq!(render(&mut buffer, &commands), NeedsRedraw::Yes);
+    assert_eq!(buffer.damage.len(), 1);
+    assert_eq!(buffer.damage[0].x, 0..480);
+    assert_eq!(buffer.damage[0].y, 0..320);
+    // The sprite is entirely outside the new sheet.
+    assert!(buffer.buffer.iter().all(|&pixel| pixel == colours::BLACK));
 
-                let should_write =
-                    wide::lt_mask_32!(
-                        wide_xs,
-                        wide_x_end
-                    );
+    assert_eq!(render(&mut buffer, &commands), NeedsRedraw::No);
+}
 
-                // Don't need to mask the shifted in zeroes.
-                let gfx_colour_a = wide::right_shift_32!(
-                    gfx_colours,
-                    24
-                );
+#[cfg(test)]
+mod damage {
+    use super::*;
 
-                let gfx_colour_r = wide::and!(
-                    wide::right_shift_32!(
-                        gfx_colours,
-                        16
-                    ),
-                    wide_255_i32
-                );
+    fn sprite_command(x: unscaled::Inner, y: unscaled::Inner) -> Command {
+        match Command::clipped(
+            unscaled::Rect {
+                x: unscaled::X(x),
+                y: unscaled::Y(y),
+                w: unscaled::W(16),
+                h: unscaled::H(16),
+            },
+            sprite::XY {
+                x: sprite::X(128),
+                y: sprite::Y(0),
+            },
+            0,
+            Flip::NONE,
+        ) {
+            Ok(command) => command,
+            Err(_) => panic!("{:?} was clipped away", (x, y)),
+        }
+    }
 
-                let gfx_colour_g = wide::and!(
-                    wide::right_shift_32!(
-                        gfx_colours,
-                        8
-                    ),
-                    wide_255_i32
-                );
+    pub(super) fn scene(moving_x: unscaled::Inner) -> Vec<Command> {
+        let mut commands = Vec::new();
 
-                // Don't need to shift since it's already in the right spot
-                let gfx_colour_b = wide::and!(
-                    gfx_colours,
-                    wide_255_i32
-                );
+        for y in (0..command::DEFAULT_HEIGHT).step_by(40) {
+            for x in (0..command::DEFAULT_WIDTH).step_by(40) {
+                commands.push(sprite_command(x, y));
+            }
+        }
 
-                // Don't need to mask the shifted in zeroes.
-                let under_a = wide::right_shift_32!(
-                    unders,
-                    24
-                );
+        commands.push(sprite_command(moving_x, 100));
 
-                let under_r = wide::and!(
-                    wide::right_shift_32!(
-                        unders,
-                        16
-                    ),
-                    wide_255_i32
-                );
+        commands
+    }
 
-                let under_g = wide::and!(
-                    wide::right_shift_32!(
-                        unders,
-                        8
-                    ),
-                    wide_255_i32
-                );
+    #[test]
+    fn rendering_the_same_commands_twice_does_not_need_a_redraw() {
+        let mut buffer = FrameBuffer::from_size((480, 320));
 
-                // Don't need to shift since it's already in the right spot
-                let under_b = wide::and!(
-                    unders,
-                    wide_255_i32
-                );
+        assert!(render(&mut buffer, &scene(5)) == NeedsRedraw::Yes);
+        assert!(render(&mut buffer, &scene(5)) == NeedsRedraw::No);
+        assert!(buffer.damage.is_empty());
+    }
 
-                // gamma to linear
-                let mut a_g = wide::mul!(
-                    wide::u32_to_f32!(
-                        gfx_colour_a
-                    ),
-                    wide_inv_255_f32
-                );
-                a_g = wide::mul!(a_g, a_g);
-                let mut r_g = wide::mul!(
-                    wide::u32_to_f32!(
-                        gfx_colour_r
-                    ),
-                    wide_inv_255_f32
-                );
-                r_g = wide::mul!(r_g, r_g);
-                let mut g_g = wide::mul!(
-                    wide::u32_to_f32!(
-                        gfx_colour_g
-                    ),
-                    wide_inv_255_f32
-                );
-                g_g = wide::mul!(g_g, g_g);
-                let mut b_g = wide::mul!(
-                    wide::u32_to_f32!(
-                        gfx_colour_b
-                    ),
-                    wide_inv_255_f32
-                );
-                b_g = wide::mul!(b_g, b_g);
+    #[test]
+    fn moving_one_command_only_damages_the_tiles_it_was_and_is_in() {
+        let mut buffer = FrameBuffer::from_size((480, 320));
 
-                let mut a_u = wide::mul!(
-                    wide::u32_to_f32!(
-                        under_a
-                    ),
-                    wide_inv_255_f32
-                );
-                a_u = wide::mul!(a_u, a_u);
-                let mut r_u = wide::mul!(
-                    wide::u32_to_f32!(
-                        under_r
-                    ),
-                    wide_inv_255_f32
-                );
-                r_u = wide::mul!(r_u, r_u);
-                let mut g_u = wide::mul!(
-                    wide::u32_to_f32!(
-                        under_g
-                    ),
-                    wide_inv_255_f32
-                );
-                g_u = wide::mul!(g_u, g_u);
-                let mut b_u = wide::mul!(
-                    wide::u32_to_f32!(
-                        under_b
-                    ),
-                    wide_inv_255_f32
-                );
-                b_u = wide::mul!(b_u, b_u);
+        render(&mut buffer, &scene(5));
+        assert!(render(&mut buffer, &scene(6)) == NeedsRedraw::Yes);
 
-                // perform alpha blending
-                let o_a = wide::add_f32!(
-                    a_g,
-                    wide::mul!(
-                        a_u,
-                        wide::sub!(wide_1_f32, a_g)
-                    )
-                );
+        assert_eq!(buffer.damage.len(), 1);
+        let damaged = &buffer.damage[0];
+        assert_eq!(damaged.x, 0..tile::SIZE);
+        assert_eq!(damaged.y, (3 * tile::SIZE)..(4 * tile::SIZE));
+    }
 
-                let inv_o_a = wide::recip!(o_a);
+    #[test]
+    fn changing_only_the_sheet_damages_the_tiles_the_command_is_in() {
+        let mut buffer = FrameBuffer::from_size((480, 320));
 
-                let one_minus_a_g = wide::sub!(wide_1_f32, a_g);
+        render(&mut buffer, &scene(5));
 
-                let o_r = wide::mul!(
-                    wide::add_f32!(
-                        wide::mul!(r_g, a_g),
-                        wide::mul!(
-                            r_u,
-                            one_minus_a_g
-                        )
-                    ),
-                    inv_o_a
-                );
-                let o_g = wide::mul!(
-                    wide::add_f32!(
-                        wide::mul!(g_g, a_g),
-                        wide::mul!(
-                            g_u,
-                            one_minus_a_g
-                        )
-                    ),
-                    inv_o_a
-                );
-                let o_b = wide::mul!(
-                    wide::add_f32!(
-                        wide::mul!(b_g, a_g),
-                        wide::mul!(
-                            b_u,
-                            one_minus_a_g
-                        )
-                    ),
-                    inv_o_a
-                );
+        let mut commands = scene(5);
+        if let Some(last) = commands.last_mut() {
+            last.sheet = sprite::SheetId::Characters;
+        }
 
-                // linear to gamma
-                let rendered_a = wide::f32_to_u32!(
-                    wide::mul!(
-                        wide_255_f32,
-                        wide::sqrt!(o_a)
-                    )
-                );
-                let rendered_r = wide::f32_to_u32!(
-                    wide::mul!(
-                        wide_255_f32,
-                        wide::sqrt!(o_r)
-                    )
-                );
-                let rendered_g = wide::f32_to_u32!(
-                    wide::mul!(
-                        wide_255_f32,
-                        wide::sqrt!(o_g)
-                    )
-                );
-                let rendered_b = wide::f32_to_u32!(
-                    wide::mul!(
-                        wide_255_f32,
-                        wide::sqrt!(o_b)
-                    )
-                );
+        assert!(render(&mut buffer, &commands) == NeedsRedraw::Yes);
+        assert_eq!(buffer.damage.len(), 1);
+    }
 
-                let rendered = wide::or!(
-                    wide::or!(
-                        wide::left_shift_32!(rendered_a, 24),
-                        wide::left_shift_32!(rendered_r, 16),
-                    ),
-                    wide::or!(
-                        wide::left_shift_32!(rendered_g, 8),
-                        rendered_b,
-                    )
-                );
+    #[test]
+    fn tiles_and_whole_frame_modes_produce_the_same_buffer() {
+        let mut tiles = FrameBuffer::from_size((960, 640));
+        let mut whole_frame = FrameBuffer::from_size((960, 640));
+        whole_frame.damage_mode = DamageMode::WholeFrame;
 
-                let to_store = wide::pick_via_mask!(
-                    unders,
-                    rendered,
-                    should_write
-                );
+        for moving_x in [5, 37, 38, 200, -10] {
+            let commands = scene(moving_x);
 
-                // SAFETY: The pointers produced by the code generated by
-                // this macro is valid to write 128 bytes to.
-                unsafe {
-                    wide::store!(
-                        to_store,
-                        frame_buffer.unscaled_buffer.as_mut_ptr(),
-                        dest_index,
-                    );
-                }
+            render(&mut tiles, &commands);
+            render(&mut whole_frame, &commands);
+
+            assert!(tiles.buffer == whole_frame.buffer, "{moving_x}");
+        }
+    }
+
+    #[test]
+    #[ignore = "benchmark: run with `cargo test --release -p render -- --ignored --nocapture`"]
+    fn bench_damage_modes() {
+        const FRAMES: unscaled::Inner = 400;
 
-                x_iter_count += wide::WIDTH as usize;
-                x += wide::WIDTH as u16;
+        for mode in [DamageMode::WholeFrame, DamageMode::Tiles] {
+            let mut buffer = FrameBuffer::from_size((960, 640));
+            buffer.damage_mode = mode;
+
+            let start = std::time::Instant::now();
+            for frame in 0..FRAMES {
+                render(&mut buffer, &scene(frame % command::DEFAULT_WIDTH));
             }
+            let elapsed = start.elapsed();
 
-            y_iter_count += 1;
+            println!(
+                "{mode:?}: {FRAMES} frames in {elapsed:?}, {:?} per frame",
+                elapsed / FRAMES as u32
+            );
         }
     }
+}
+
+#[cfg(test)]
+mod scaling {
+    use super::*;
+    use damage::scene;
+
+    #[test]
+    fn viewport_places_the_frame_as_expected_in_these_cases() {
+        let rects = |mode, size| {
+            let viewport = Viewport::new(mode, size, command::Size::DEFAULT);
+            (viewport.rect.x, viewport.rect.y)
+        };
+
+        assert_eq!(rects(ScaleMode::Integer, (1000, 700)), (20..980, 30..670));
+        // Smaller than the unscaled frame, so it scales down to fit instead.
+        assert_eq!(rects(ScaleMode::Integer, (240, 200)), (0..240, 20..180));
+        assert_eq!(
+            rects(ScaleMode::Fit(Filter::Bilinear), (1000, 700)),
+            (0..1000, 17..684)
+        );
+        assert_eq!(
+            rects(ScaleMode::Fit(Filter::Bilinear), (1000, 500)),
+            (125..875, 0..500)
+        );
+        assert_eq!(
+            rects(ScaleMode::Stretch(Filter::Bilinear), (1000, 500)),
+            (0..1000, 0..500)
+        );
+        assert_eq!(rects(ScaleMode::Integer, (0, 0)), (0..0, 0..0));
+    }
+
+    #[test]
+    fn viewport_follows_the_logical_size_in_these_cases() {
+        let Some(widescreen) = command::Size::new(320, 180) else {
+            panic!("320x180 should be a valid size");
+        };
+
+        let rects = |mode, size| {
+            let viewport = Viewport::new(mode, size, widescreen);
+            (viewport.rect.x, viewport.rect.y)
+        };
+
+        assert_eq!(rects(ScaleMode::Integer, (1920, 1080)), (0..1920, 0..1080));
+        assert_eq!(rects(ScaleMode::Integer, (1000, 700)), (20..980, 80..620));
+        assert_eq!(
+            rects(ScaleMode::Fit(Filter::Bilinear), (1000, 700)),
+            (0..1000, 69..632)
+        );
+    }
+
+    #[test]
+    fn tiles_and_whole_frame_modes_produce_the_same_buffer_at_other_logical_sizes() {
+        for (logical_w, logical_h) in [(320, 180), (640, 360), (100, 75)] {
+            let Some(logical_size) = command::Size::new(logical_w, logical_h) else {
+                panic!("{logical_w}x{logical_h} should be a valid size");
+            };
 
-    // TODO If we need some more render speed, this could be made wide as well.
-    let mut src_i = 0;
-    let mut src_i_row_start;
-    let mut y_remaining = multiplier;
-    for y in outer_clip_rect.y {
-        let mut x_remaining = multiplier;
+            let mut tiles = FrameBuffer::new((700, 500), logical_size);
+            let mut whole_frame = FrameBuffer::new((700, 500), logical_size);
+            whole_frame.damage_mode = DamageMode::WholeFrame;
 
-        src_i_row_start = src_i;
+            for moving_x in [5, 37, 38, 200, -10, 630] {
+                let commands = scene(moving_x);
 
-        for x in outer_clip_rect.x.clone() {
-            let d_i = usize::from(y)
-            * usize::from(frame_buffer.width)
-            + usize::from(x);
+                render(&mut tiles, &commands);
+                render(&mut whole_frame, &commands);
 
-            if d_i < frame_buffer.buffer.len() {
-                frame_buffer.buffer[d_i] =
-                    frame_buffer.unscaled_buffer[src_i];
+                assert!(
+                    tiles.buffer == whole_frame.buffer,
+                    "{logical_size:?} {moving_x}"
+                );
             }
+        }
+    }
 
-            x_remaining -= 1;
-            if x_remaining == 0 {
-                src_i += 1;
-                x_remaining = multiplier;
+    #[test]
+    fn the_weights_for_each_output_pixel_sum_to_1_in_these_cases() {
+        for filter in [Filter::Nearest, Filter::Bilinear, Filter::SharpBilinear] {
+            for dest_len in [1, 100, 239, 480, 481, 700, 960, 1921] {
+                let axis = Axis::new(480, dest_len, filter);
+
+                assert_eq!(axis.starts.len(), usize::from(dest_len));
+                for weights in axis.weights.chunks(axis.taps) {
+                    let sum: f32 = weights.iter().sum();
+                    assert!(
+                        (sum - 1.).abs() < 0.0001,
+                        "{filter:?} {dest_len} {weights:?}"
+                    );
+                }
             }
         }
+    }
+
+    #[test]
+    fn sharp_bilinear_and_nearest_match_integer_scaling_at_whole_number_scales() {
+        let commands = scene(5);
 
-        src_i = src_i_row_start;
+        let mut integer = FrameBuffer::from_size((960, 640));
+        render(&mut integer, &commands);
 
-        y_remaining -= 1;
-        if y_remaining == 0 {
-            y_remaining = multiplier;
-            let src_w = usize::from(command::WIDTH as u16);
-            src_i += src_w;
+        for filter in [Filter::Nearest, Filter::SharpBilinear] {
+            let mut filtered = FrameBuffer::from_size((960, 640));
+            filtered.scale_mode = ScaleMode::Fit(filter);
+            render(&mut filtered, &commands);
+
+            assert!(integer.buffer == filtered.buffer, "{filter:?}");
         }
     }
 
-    frame_buffer.cells.swap();
+    #[test]
+    fn tiles_and_whole_frame_modes_produce_the_same_buffer_when_filtering() {
+        for scale_mode in [
+            ScaleMode::Fit(Filter::SharpBilinear),
+            ScaleMode::Stretch(Filter::Bilinear),
+            ScaleMode::Integer,
+        ] {
+            for size in [(700, 500), (300, 170)] {
+                let mut tiles = FrameBuffer::from_size(size);
+                tiles.scale_mode = scale_mode;
+                let mut whole_frame = FrameBuffer::from_size(size);
+                whole_frame.scale_mode = scale_mode;
+                whole_frame.damage_mode = DamageMode::WholeFrame;
+
+                for moving_x in [5, 37, 38, 200, -10] {
+                    let commands = scene(moving_x);
+
+                    render(&mut tiles, &commands);
+                    render(&mut whole_frame, &commands);
+
+                    assert!(
+                        tiles.buffer == whole_frame.buffer,
+                        "{scale_mode:?} {size:?} {moving_x}"
+                    );
+                }
+            }
+        }
+    }
 
-    NeedsRedraw::Yes
+    #[test]
+    fn changing_the_scale_mode_redraws_the_whole_buffer() {
+        let mut buffer = FrameBuffer::from_size((700, 500));
+
+        render(&mut buffer, &scene(5));
+        buffer.scale_mode = ScaleMode::Stretch(Filter::Nearest);
+
+        assert!(render(&mut buffer, &scene(5)) == NeedsRedraw::Yes);
+        assert_eq!(buffer.damage.len(), 1);
+        assert_eq!(buffer.damage[0].x, 0..700);
+        assert_eq!(buffer.damage[0].y, 0..500);
+    }
 }
 
-#[test]
-fn render_does_not_panic_on_these_examples() {
-    let commands = [
-        Command {
-            rect: Rect {
-                x_min: command::X::clipped_inner(-166),
-                y_min: command::Y::clipped_inner(96),
-                x_max: command::X::clipped_inner(-56),
-                y_max: command::Y::clipped_inner(223),
-            },
-            sprite_xy: sprite::XY { x: sprite::X(128), y: sprite::Y(128) },
-            .. Command::default()
-        },
-    ];
+#[cfg(all(test, feature = "post-process"))]
+mod post_process {
+    use super::*;
+    use damage::scene;
+    use post::PostProcess;
 
-    let mut buffer = FrameBuffer::from_size((400, 300));
+    #[test]
+    fn tiles_and_whole_frame_modes_produce_the_same_buffer_when_post_processing() {
+        for post_process in [PostProcess::CRT, PostProcess::DITHERED] {
+            for scale_mode in [ScaleMode::Integer, ScaleMode::Fit(Filter::Bilinear)] {
+                let mut tiles = FrameBuffer::from_size((700, 500));
+                tiles.post_process = post_process;
+                tiles.scale_mode = scale_mode;
+                let mut whole_frame = FrameBuffer::from_size((700, 500));
+                whole_frame.post_process = post_process;
+                whole_frame.scale_mode = scale_mode;
+                whole_frame.damage_mode = DamageMode::WholeFrame;
+
+                for moving_x in [5, 37, 38, 200, -10] {
+                    let commands = scene(moving_x);
+
+                    render(&mut tiles, &commands);
+                    render(&mut whole_frame, &commands);
+
+                    assert!(
+                        tiles.buffer == whole_frame.buffer,
+                        "{post_process:?} {scale_mode:?} {moving_x}"
+                    );
+                }
+            }
+        }
+    }
 
-    render(&mut buffer, &commands);
-}
\ No newline at end of file
+    #[test]
+    fn changing_the_post_process_redraws_the_whole_frame() {
+        let mut buffer = FrameBuffer::from_size((480, 320));
+        render(&mut buffer, &scene(5));
+        let plain = buffer.buffer.clone();
+
+        buffer.post_process = PostProcess::CRT;
+        assert!(render(&mut buffer, &scene(5)) == NeedsRedraw::Yes);
+        assert!(buffer.buffer != plain);
+
+        buffer.post_process = PostProcess::OFF;
+        assert!(render(&mut buffer, &scene(5)) == NeedsRedraw::Yes);
+        assert!(buffer.buffer == plain);
+    }
+
+    #[test]
+    fn dither_only_uses_the_given_number_of_levels() {
+        let logical_size = command::Size::DEFAULT;
+        let mut unscaled_buffer = vec![0; logical_size.length()];
+        for (i, pixel) in unscaled_buffer.iter_mut().enumerate() {
+            *pixel = 0xFF00_0000 | (i as u32).wrapping_mul(0x9E37_79B9) >> 8;
+        }
+
+        post::dither(
+            &mut unscaled_buffer,
+            logical_size,
+            4,
+            &clip::Rect {
+                x: 0..logical_size.width() as clip::X,
+                y: 0..logical_size.height() as clip::Y,
+            },
+        );
+
+        for pixel in unscaled_buffer.iter() {
+            for shift in [0, 8, 16] {
+                let channel = pixel >> shift & 255;
+                assert!(
+                    [0, 85, 170, 255].contains(&channel),
+                    "{pixel:08X}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,365 @@
+//! Immediate mode menus. Each frame, make a `Ui`, then call the method for
+//! each widget, top to bottom. Each widget draws itself, and reports what
+//! the player did to it, if anything, that frame.
+//!
+//! The focus moves between the widgets that can be interacted with using
+//! `Button::UP` and `Button::DOWN`, which repeat while held. `Button::A`
+//! activates the focused widget and `Button::LEFT` and `Button::RIGHT`
+//! adjust it. `Button::B` does nothing by itself, but `Ui::back` reports it
+//! so menus can close.
+
+use gfx::{CHAR_H, Commands, text};
+use platform_types::{Button, Input, PaletteIndex, unscaled::{self, H, W, X, Y}};
+
+/// Which widget has the focus, and how many widgets could have had it last
+/// frame. Keep one of these for each menu, from one frame to the next.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Focus {
+    index: usize,
+    count: usize,
+}
+
+impl Focus {
+    /// The position of the focused widget among the ones that can be
+    /// focused, in the order they were added.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn set_index(&mut self, index: usize) {
+        self.index = index;
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Style {
+    pub colour: PaletteIndex,
+    pub focused_colour: PaletteIndex,
+    /// The space between each widget and the next one.
+    pub spacing: H,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            // White
+            colour: 6,
+            // Yellow
+            focused_colour: 3,
+            spacing: H(4),
+        }
+    }
+}
+
+/// Drawn to the left of the focused widget.
+const CURSOR: &str = "→";
+/// Drawn to the left of the selected item in a `Ui::list`.
+const SELECTED: &str = "•";
+/// How many characters wide the bar of a `Ui::slider` is.
+const SLIDER_STEPS: usize = 10;
+
+pub struct Ui<'commands, 'focus> {
+    commands: &'commands mut Commands,
+    input: Input,
+    focus: &'focus mut Focus,
+    style: Style,
+    /// The index the next widget that can be focused will get.
+    next_index: usize,
+    x: X,
+    y: Y,
+}
+
+impl <'commands, 'focus> Ui<'commands, 'focus> {
+    /// Starts a menu with its top left corner at `xy`. If `Button::UP` or
+    /// `Button::DOWN` was pressed this frame, the focus moves, wrapping
+    /// around at the ends.
+    pub fn new(
+        commands: &'commands mut Commands,
+        input: Input,
+        focus: &'focus mut Focus,
+        xy: unscaled::XY,
+        style: Style,
+    ) -> Self {
+        if focus.count > 0 {
+            if input.pressed_or_repeated_this_frame(Button::UP) {
+                focus.index = (focus.index + focus.count - 1) % focus.count;
+            }
+            if input.pressed_or_repeated_this_frame(Button::DOWN) {
+                focus.index = (focus.index + 1) % focus.count;
+            }
+        }
+
+        Self {
+            commands,
+            input,
+            focus,
+            style,
+            next_index: 0,
+            x: xy.x,
+            y: xy.y,
+        }
+    }
+
+    /// Gives the next widget an index, and returns whether it is focused.
+    fn take_focus(&mut self) -> bool {
+        let focused = self.next_index == self.focus.index;
+        self.next_index += 1;
+        focused
+    }
+
+    fn pressed(&self, focused: bool, button: Button) -> bool {
+        focused && self.input.pressed_this_frame(button)
+    }
+
+    fn row(&mut self, text: &str, focused: bool, indent: W) {
+        let colour = if focused {
+            self.style.focused_colour
+        } else {
+            self.style.colour
+        };
+
+        if focused {
+            self.commands.print_line(CURSOR, self.x, self.y, colour);
+        }
+
+        self.commands.print_line(
+            text,
+            self.x + cursor_w() + indent,
+            self.y,
+            colour,
+        );
+
+        self.y += CHAR_H + self.style.spacing;
+    }
+
+    /// Text that can't be focused.
+    pub fn label(&mut self, text: &str) {
+        self.row(text, false, W(0));
+    }
+
+    /// Leaves a gap before the next widget, on top of the usual spacing.
+    pub fn space(&mut self, h: H) {
+        self.y += h;
+    }
+
+    /// Returns whether the button was activated this frame.
+    pub fn button(&mut self, text: &str) -> bool {
+        let focused = self.take_focus();
+        self.row(text, focused, W(0));
+
+        self.pressed(focused, Button::A)
+    }
+
+    /// Flips `on` when activated, or adjusted in either direction. Returns
+    /// whether `on` changed.
+    pub fn toggle(&mut self, text: &str, on: &mut bool) -> bool {
+        let focused = self.take_focus();
+
+        let changed = self.pressed(focused, Button::A)
+            || self.pressed(focused, Button::LEFT)
+            || self.pressed(focused, Button::RIGHT);
+        if changed {
+            *on = !*on;
+        }
+
+        self.row(
+            &format!("{text}: {}", if *on { "on" } else { "off" }),
+            focused,
+            W(0),
+        );
+
+        changed
+    }
+
+    /// Moves `value` down or up by one, between 0 and `max`, when adjusted.
+    /// Returns whether `value` changed.
+    pub fn slider(&mut self, text: &str, value: &mut u8, max: u8) -> bool {
+        let focused = self.take_focus();
+
+        let old = *value;
+        if focused && self.input.pressed_or_repeated_this_frame(Button::LEFT) {
+            *value = value.saturating_sub(1);
+        }
+        if focused && self.input.pressed_or_repeated_this_frame(Button::RIGHT) {
+            *value = value.saturating_add(1);
+        }
+        *value = core::cmp::min(*value, max);
+
+        self.row(&format!("{text} {}", slider_bar(*value, max)), focused, W(0));
+
+        *value != old
+    }
+
+    /// Each of `items` gets its own row, and can be focused. Activating one
+    /// sets `selected` to its index. Returns whether `selected` changed.
+    pub fn list(&mut self, items: &[&str], selected: &mut usize) -> bool {
+        let old = *selected;
+        let indent = text::line_width(SELECTED) + W(2);
+
+        for (i, item) in items.iter().enumerate() {
+            let focused = self.take_focus();
+            if self.pressed(focused, Button::A) {
+                *selected = i;
+            }
+
+            if i == *selected {
+                self.commands.print_line(
+                    SELECTED,
+                    self.x + cursor_w(),
+                    self.y,
+                    self.style.colour,
+                );
+            }
+
+            self.row(item, focused, indent);
+        }
+
+        *selected != old
+    }
+
+    /// Whether `Button::B` was pressed this frame, which menus should treat
+    /// as asking to go back.
+    pub fn back(&self) -> bool {
+        self.input.pressed_this_frame(Button::B)
+    }
+}
+
+impl Drop for Ui<'_, '_> {
+    fn drop(&mut self) {
+        self.focus.count = self.next_index;
+        if self.focus.index >= self.focus.count {
+            self.focus.index = self.focus.count.saturating_sub(1);
+        }
+    }
+}
+
+/// How far right of the menu's left edge the widgets' text starts, leaving
+/// room for the cursor.
+fn cursor_w() -> W {
+    text::line_width(CURSOR) + W(2)
+}
+
+fn slider_bar(value: u8, max: u8) -> String {
+    let filled = if max == 0 {
+        0
+    } else {
+        usize::from(value) * SLIDER_STEPS / usize::from(max)
+    };
+
+    format!("[{}{}]", "=".repeat(filled), "-".repeat(SLIDER_STEPS - filled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pressing(button: Button) -> Input {
+        let mut input = Input::default();
+        input.gamepad = button;
+        input
+    }
+
+    fn ui<'commands, 'focus>(
+        commands: &'commands mut Commands,
+        focus: &'focus mut Focus,
+        input: Input,
+    ) -> Ui<'commands, 'focus> {
+        Ui::new(commands, input, focus, unscaled::XY::default(), Style::default())
+    }
+
+    #[test]
+    fn the_focus_wraps_around_and_skips_labels() {
+        let mut commands = Commands::default();
+        let mut focus = Focus::default();
+
+        for (input, expected) in [
+            (Input::default(), 0),
+            (pressing(Button::UP), 2),
+            (pressing(Button::DOWN), 0),
+            (pressing(Button::DOWN), 1),
+        ] {
+            let mut ui = ui(&mut commands, &mut focus, input);
+            ui.label("title");
+            ui.button("a");
+            ui.button("b");
+            ui.button("c");
+            drop(ui);
+
+            assert_eq!(focus.index(), expected);
+        }
+    }
+
+    #[test]
+    fn only_the_focused_button_is_activated() {
+        let mut commands = Commands::default();
+        let mut focus = Focus::default();
+        focus.set_index(1);
+
+        let mut ui = ui(&mut commands, &mut focus, pressing(Button::A));
+        assert!(!ui.button("a"));
+        assert!(ui.button("b"));
+        assert!(!ui.button("c"));
+    }
+
+    #[test]
+    fn the_focus_stays_in_range_when_widgets_go_away() {
+        let mut commands = Commands::default();
+        let mut focus = Focus::default();
+        focus.set_index(5);
+
+        let mut ui = ui(&mut commands, &mut focus, Input::default());
+        ui.button("a");
+        ui.button("b");
+        drop(ui);
+
+        assert_eq!(focus.index(), 1);
+    }
+
+    #[test]
+    fn sliders_stay_between_zero_and_max() {
+        let mut commands = Commands::default();
+        let mut focus = Focus::default();
+
+        let mut value = 3;
+        assert!(ui(&mut commands, &mut focus, pressing(Button::LEFT))
+            .slider("volume", &mut value, 3));
+        assert_eq!(value, 2);
+
+        assert!(ui(&mut commands, &mut focus, pressing(Button::RIGHT))
+            .slider("volume", &mut value, 3));
+        assert!(!ui(&mut commands, &mut focus, pressing(Button::RIGHT))
+            .slider("volume", &mut value, 3));
+        assert_eq!(value, 3);
+
+        let mut value = 0;
+        assert!(!ui(&mut commands, &mut focus, pressing(Button::LEFT))
+            .slider("volume", &mut value, 3));
+        assert_eq!(value, 0);
+    }
+
+    #[test]
+    fn toggles_and_lists_change_their_values() {
+        let mut commands = Commands::default();
+        let mut focus = Focus::default();
+
+        let mut on = false;
+        assert!(ui(&mut commands, &mut focus, pressing(Button::A))
+            .toggle("music", &mut on));
+        assert!(on);
+
+        let mut selected = 0;
+        focus.set_index(2);
+        let mut ui = ui(&mut commands, &mut focus, pressing(Button::A));
+        assert!(ui.list(&["small", "medium", "large"], &mut selected));
+        drop(ui);
+        assert_eq!(selected, 2);
+    }
+
+    #[test]
+    fn slider_bar_is_always_the_same_width() {
+        assert_eq!(slider_bar(0, 3), "[----------]");
+        assert_eq!(slider_bar(3, 3), "[==========]");
+        assert_eq!(slider_bar(0, 0), "[----------]");
+        assert_eq!(slider_bar(2, 4).len(), SLIDER_STEPS + 2);
+    }
+}
@@ -1,81 +1,485 @@
-//Read in the png and output the data as a text array
+//Read in the png and output the data in the compressed format that
+//`assets` decodes. See the docs on `assets::decode` for the details.
+extern crate platform_types;
 extern crate png;
 
+mod manifest;
+
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
 
-const IMAGE_FILENAME: &'static str = "../../assets/gfx.png";
-// for testing
-// const IMAGE_FILENAME: &'static str = "assets/pallete.png";
+use platform_types::{
+    FONT_HEIGHT,
+    FONT_WIDTH,
+    GFX_HEIGHT,
+    GFX_WIDTH,
+    PALETTE,
+    sprite::SheetId,
+};
+
+pub struct Sheet {
+    pub image: &'static str,
+    /// Where to write the data for `image` to.
+    pub output: &'static str,
+    /// The size `image` must be.
+    pub size: (usize, usize),
+    /// The `SheetId`s that `assets` uses this image for.
+    pub ids: &'static [SheetId],
+}
+
+/// Each sprite sheet in `assets` can use a different image. These are
+/// converted when no files are passed on the command line.
+pub const SHEETS: &[Sheet] = &[
+    Sheet {
+        image: "../../assets/gfx.png",
+        output: "../../libs/assets/src/gfx.bin",
+        size: (GFX_WIDTH, GFX_HEIGHT),
+        ids: &[SheetId::Tiles, SheetId::Characters, SheetId::Ui],
+    },
+    Sheet {
+        image: "../../assets/font.png",
+        output: "../../libs/assets/src/font.bin",
+        size: (FONT_WIDTH, FONT_HEIGHT),
+        ids: &[SheetId::Font],
+    },
+    // for testing
+    // Sheet {
+    //     image: "assets/pallete.png",
+    //     output: "out.txt",
+    //     size: (8, 1),
+    //     ids: &[],
+    // },
+];
+
+pub fn sheet_for(id: SheetId) -> Option<&'static Sheet> {
+    SHEETS.iter().find(|sheet| sheet.ids.contains(&id))
+}
+
+/// The manifest of named sprite regions, and the module to generate from it.
+const MANIFEST: (&str, &str) = (
+    "../../assets/sprites.toml",
+    "../../libs/platform_types/src/sprites.rs",
+);
+
+const USAGE: &str = "\
+usage: update_gfx [OPTIONS] [INPUT OUTPUT]...
+
+Converts each INPUT png file into the format that `assets` embeds, and writes
+it to OUTPUT. With no files given, converts the sprite sheets the game uses,
+and generates the sprite regions module from `assets/sprites.toml`, which
+expects to be run from the `tooling/update_gfx` folder.
+
+options:
+    --manifest IN OUT    Generate a Rust module with the regions in the IN
+                         manifest, and write it to OUT.
+    --size WIDTHxHEIGHT  The size every INPUT image must be. Defaults to the
+                         size of the main sprite sheet, from `platform_types`.
+    --any-size           Allow INPUT images of any size.
+    --report-limit N     How many colours that are not in the palette to list
+                         for each image. Defaults to 16.
+    --strict-palette     Fail if any visible pixels are not in the palette.
+    -h, --help           Print this message.
+";
+
+/// An image to convert.
+struct Job {
+    input: String,
+    /// Where to write the converted image to.
+    output: String,
+    /// The size `input` must be. `None` means any size is allowed.
+    size: Option<(u32, u32)>,
+}
+
+struct Options {
+    files: Vec<Job>,
+    manifests: Vec<(String, String)>,
+    /// `None` means any size is allowed.
+    expected_size: Option<(u32, u32)>,
+    report_limit: usize,
+    strict_palette: bool,
+}
+
+/// Returns `Ok(None)` if the usage was asked for.
+fn parse_args(
+    mut args: impl Iterator<Item = String>
+) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        files: Vec::new(),
+        manifests: Vec::new(),
+        expected_size: Some((GFX_WIDTH as u32, GFX_HEIGHT as u32)),
+        report_limit: 16,
+        strict_palette: false,
+    };
+
+    let mut paths = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--size" => {
+                let size = args.next()
+                    .ok_or_else(|| "--size needs a value".to_string())?;
+
+                let parsed = {
+                    let mut parts = size.splitn(2, 'x');
+                    match (
+                        parts.next().and_then(|w| w.parse().ok()),
+                        parts.next().and_then(|h| h.parse().ok()),
+                    ) {
+                        (Some(w), Some(h)) => Some((w, h)),
+                        _ => None,
+                    }
+                };
+
+                options.expected_size = Some(parsed.ok_or_else(|| format!(
+                    "Expected a size like \"512x1024\", not \"{}\"",
+                    size
+                ))?);
+            },
+            "--manifest" => match (args.next(), args.next()) {
+                (Some(input), Some(output)) => {
+                    options.manifests.push((input, output));
+                },
+                _ => return Err("--manifest needs an input and an output".to_string()),
+            },
+            "--any-size" => options.expected_size = None,
+            "--report-limit" => {
+                let limit = args.next()
+                    .ok_or_else(|| "--report-limit needs a value".to_string())?;
+
+                options.report_limit = limit.parse().map_err(|_| format!(
+                    "Expected a number of colours, not \"{}\"",
+                    limit
+                ))?;
+            },
+            "--strict-palette" => options.strict_palette = true,
+            _ if arg.starts_with('-') => {
+                return Err(format!("Unknown option \"{}\"", arg));
+            },
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.len() % 2 != 0 {
+        return Err(format!(
+            "\"{}\" needs an output file to go with it",
+            paths[paths.len() - 1]
+        ));
+    }
+
+    let mut paths = paths.into_iter();
+    while let (Some(input), Some(output)) = (paths.next(), paths.next()) {
+        options.files.push(Job {
+            input,
+            output,
+            size: options.expected_size,
+        });
+    }
+
+    if options.files.is_empty() && options.manifests.is_empty() {
+        options.files = SHEETS.iter()
+            .map(|sheet| Job {
+                input: sheet.image.to_string(),
+                output: sheet.output.to_string(),
+                size: Some((sheet.size.0 as u32, sheet.size.1 as u32)),
+            })
+            .collect();
+        options.manifests.push((MANIFEST.0.to_string(), MANIFEST.1.to_string()));
+    }
 
-const OUTPUT_FILENAME: &'static str = "../../libs/assets/src/gfx.in";
-// for testing
-// const OUTPUT_FILENAME: &'static str = "out.txt";
+    Ok(Some(options))
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let decoder = png::Decoder::new(File::open(IMAGE_FILENAME)?);
-    let (info, mut reader) = decoder.read_info()?;
-    println!(
-        "{} : {:?}",
-        IMAGE_FILENAME,
-        (
-            info.width,
-            info.height,
-            info.color_type,
-            info.bit_depth,
-            info.line_size
-        )
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return Ok(());
+        },
+        Err(message) => {
+            eprint!("{}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        },
+    };
+
+    for job in &options.files {
+        convert(&job.input, &job.output, job.size, &options)?;
+    }
+
+    for (manifest_filename, output_filename) in &options.manifests {
+        manifest::convert(manifest_filename, output_filename)?;
+    }
+
+    Ok(())
+}
+
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// ARGB, row by row.
+    pub pixels: Vec<u32>,
+}
+
+impl Image {
+    pub fn get(&self, x: u32, y: u32) -> u32 {
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+pub fn load(image_filename: &str) -> Result<Image, Box<dyn std::error::Error>> {
+    let file = File::open(image_filename)
+        .map_err(|e| format!("Could not open {}: {}", image_filename, e))?;
+    let mut decoder = png::Decoder::new(file);
+    // Turn indexed and low bit depth images into 8 bit RGB(A) or grayscale,
+    // and 16 bit images into 8 bit ones, so there are only four cases below.
+    decoder.set_transformations(
+        png::Transformations::EXPAND | png::Transformations::STRIP_16
     );
-    // Allocate the output buffer.
-    let mut buf = vec![0; info.buffer_size()];
-    // Read the next frame. Currently this function should only called once.
-    // The default options
-    reader.next_frame(&mut buf)?;
+    let mut reader = decoder.read_info()?;
+    {
+        let info = reader.info();
+        println!(
+            "{} : {:?}",
+            image_filename,
+            (
+                info.width,
+                info.height,
+                info.color_type,
+                info.bit_depth,
+            )
+        );
+    }
 
-    let output_filename = OUTPUT_FILENAME;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    let bytes = &buf[..info.buffer_size()];
 
-    let mut file = File::create(output_filename)?;
+    let argb = |a: u8, r: u8, g: u8, b: u8| -> u32 {
+        ((a as u32) << 24)
+        | ((r as u32) << 16)
+        | ((g as u32) << 8)
+        | (b as u32)
+    };
 
     use png::ColorType::*;
-    let pixel_width = match info.color_type {
-        RGBA => 4,
-        _ => unimplemented!(
-            "This program cannot handle {:?} images (yet.)",
-            info.color_type
-        ),
+    let pixels: Vec<u32> = match info.color_type {
+        Rgba => bytes.chunks(4)
+            .map(|c| argb(c[3], c[0], c[1], c[2]))
+            .collect(),
+        Rgb => bytes.chunks(3)
+            .map(|c| argb(255, c[0], c[1], c[2]))
+            .collect(),
+        GrayscaleAlpha => bytes.chunks(2)
+            .map(|c| argb(c[1], c[0], c[0], c[0]))
+            .collect(),
+        Grayscale => bytes.iter()
+            .map(|&c| argb(255, c, c, c))
+            .collect(),
+        // `EXPAND` should have turned these into `Rgb` or `Rgba`.
+        Indexed => return Err(format!(
+            "{} was not expanded from indexed colour",
+            image_filename
+        ).into()),
     };
 
-    let mut pixels = Vec::with_capacity(buf.len() / pixel_width);
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        pixels,
+    })
+}
+
+fn convert(
+    image_filename: &str,
+    output_filename: &str,
+    expected_size: Option<(u32, u32)>,
+    options: &Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let image = load(image_filename)?;
 
-    for colour in buf.chunks(pixel_width) {
-        let argb =
-        ((colour[3] as u32) << 24)
-        | ((colour[0] as u32) << 16)
-        | ((colour[1] as u32) << 8)
-        | ((colour[2] as u32));
+    if let Some((width, height)) = expected_size {
+        if (image.width, image.height) != (width, height) {
+            return Err(format!(
+                "{} is {}x{}, but it should be {}x{}. If the sprite sheet \
+                size changed, update the constants in `platform_types`.",
+                image_filename,
+                image.width,
+                image.height,
+                width,
+                height,
+            ).into());
+        }
+    }
 
-        pixels.push(argb);
+    if image.width > u32::from(u16::MAX)
+    || image.height > u32::from(u16::MAX) {
+        return Err(format!(
+            "{} is {}x{}, but the largest supported size is {}x{}",
+            image_filename,
+            image.width,
+            image.height,
+            u16::MAX,
+            u16::MAX,
+        ).into());
     }
 
-    let mut output = String::with_capacity(
-        pixels.len() * "0xFFFFFFFF, ".len()
-        // Newlines for each row
-        + 1024
-        // Extra for start and end of array
-        + 8
+    let off_palette_count = report_off_palette(
+        image_filename,
+        image.width as usize,
+        &image.pixels,
+        options.report_limit,
     );
-    output.push_str("[\n");
-    for chunk in pixels.chunks(512) {
-        for colour in chunk.iter() {
-            output.push_str(&format!("0x{colour:08X}, "));
-        }
-        output.push('\n');
+
+    if options.strict_palette && off_palette_count > 0 {
+        return Err(format!(
+            "{} has pixels that are not in the palette",
+            image_filename
+        ).into());
     }
-    output.push_str("]\n");
 
-    file.write_all(output.as_bytes())?;
+    let output = compress(image.width as u16, image.height as u16, &image.pixels)?;
+
+    let mut file = File::create(output_filename)
+        .map_err(|e| format!("Could not create {}: {}", output_filename, e))?;
+    file.write_all(&output)?;
 
     println!("overwrote {}", output_filename);
 
     Ok(())
 }
+
+/// Prints the colours in `pixels` that are not in `PALETTE`, most common
+/// first, along with where each one first shows up. Fully transparent pixels
+/// are never drawn, so their colour doesn't matter. Returns how many pixels
+/// were not in the palette.
+fn report_off_palette(
+    image_filename: &str,
+    width: usize,
+    pixels: &[u32],
+    limit: usize,
+) -> usize {
+    // Colour to (count, index of the first one).
+    let mut off_palette: HashMap<u32, (usize, usize)> = HashMap::new();
+
+    for (i, &colour) in pixels.iter().enumerate() {
+        if colour >> 24 == 0 || PALETTE.contains(&colour) {
+            continue
+        }
+
+        off_palette.entry(colour).or_insert((0, i)).0 += 1;
+    }
+
+    let total: usize = off_palette.values().map(|&(count, _)| count).sum();
+    if total == 0 {
+        return 0
+    }
+
+    println!(
+        "{} : {} pixels in {} colours are not in the palette",
+        image_filename,
+        total,
+        off_palette.len(),
+    );
+
+    let mut colours: Vec<_> = off_palette.into_iter().collect();
+    // Break ties by position, so the output is the same each time.
+    colours.sort_by_key(|&(_, (count, first))| (std::cmp::Reverse(count), first));
+
+    for &(colour, (count, first)) in colours.iter().take(limit) {
+        println!(
+            "    0x{:08X}: {} pixels, first at ({}, {})",
+            colour,
+            count,
+            first % width,
+            first / width,
+        );
+    }
+
+    if colours.len() > limit {
+        println!("    ... and {} more colours", colours.len() - limit);
+    }
+
+    total
+}
+
+const MAGIC: &[u8; 4] = b"GFX2";
+
+fn compress(
+    width: u16,
+    height: u16,
+    pixels: &[u32],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    // Colours are numbered in the order they first appear, so the output
+    // only changes where the image does.
+    let mut palette: Vec<u32> = Vec::new();
+    let mut palette_indices: HashMap<u32, usize> = HashMap::new();
+    let mut indices = Vec::with_capacity(pixels.len());
+    for &colour in pixels {
+        let index = *palette_indices.entry(colour).or_insert_with(|| {
+            palette.push(colour);
+            palette.len() - 1
+        });
+        indices.push(index);
+    }
+
+    if palette.len() > u16::MAX as usize {
+        return Err(format!(
+            "{} colours is too many. The limit is {}",
+            palette.len(),
+            u16::MAX
+        ).into());
+    }
+
+    let mut output = Vec::with_capacity(
+        MAGIC.len() + 6 + palette.len() * 5 + pixels.len() / 8
+    );
+    output.extend_from_slice(MAGIC);
+    output.extend_from_slice(&width.to_le_bytes());
+    output.extend_from_slice(&height.to_le_bytes());
+    output.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+    for &colour in &palette {
+        output.extend_from_slice(&colour.to_le_bytes());
+        // Which `PALETTE` colour to treat this one as, when drawing with
+        // another `palette::Id`.
+        output.push(platform_types::palette::nearest(colour));
+    }
+
+    let wide_indices = palette.len() > 256;
+
+    let mut i = 0;
+    while i < indices.len() {
+        let index = indices[i];
+        let mut run_length = 1;
+        while i + run_length < indices.len()
+        && indices[i + run_length] == index {
+            run_length += 1;
+        }
+        i += run_length;
+
+        // LEB128: seven bits at a time, with the high bit set on all but
+        // the last byte.
+        let mut remaining = run_length;
+        while remaining >= 0x80 {
+            output.push((remaining as u8 & 0x7F) | 0x80);
+            remaining >>= 7;
+        }
+        output.push(remaining as u8);
+
+        if wide_indices {
+            output.extend_from_slice(&(index as u16).to_le_bytes());
+        } else {
+            output.push(index as u8);
+        }
+    }
+
+    println!(
+        "{} pixels, {} colours, {} bytes",
+        pixels.len(),
+        palette.len(),
+        output.len()
+    );
+
+    Ok(output)
+}
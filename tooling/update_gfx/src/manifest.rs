@@ -0,0 +1,575 @@
+//Read in the sprite manifest and output a Rust module with a constant for
+//each named region. See `assets/sprites.toml` for the format.
+
+use std::fs::File;
+use std::io::prelude::*;
+
+use super::{Image, load, sheet_for};
+
+use platform_types::sprite::SheetId;
+
+struct Region {
+    name: String,
+    /// The line the region's table starts on, for error messages.
+    line: usize,
+    sheet: Option<String>,
+    x: Option<u32>,
+    y: Option<u32>,
+    w: Option<u32>,
+    h: Option<u32>,
+    frames: Option<u32>,
+    columns: Option<u32>,
+    x_advance: Option<u32>,
+    y_advance: Option<u32>,
+    widths: Option<bool>,
+}
+
+enum Value {
+    Integer(u32),
+    String(String),
+    Boolean(bool),
+}
+
+/// Parses the subset of TOML that the manifest uses: tables with integer,
+/// string or boolean values, and comments. As in TOML, each key can only be
+/// set once in each table.
+fn parse(manifest_filename: &str, text: &str) -> Result<Vec<Region>, String> {
+    let mut regions: Vec<Region> = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let error = |message: &str| format!(
+            "{}:{}: {}",
+            manifest_filename,
+            line_number,
+            message
+        );
+
+        // A `#` starts a comment, unless it is inside a string.
+        let comment_start = {
+            let mut in_string = false;
+            line.char_indices()
+                .find(|&(_, c)| {
+                    if c == '"' {
+                        in_string = !in_string;
+                    }
+                    c == '#' && !in_string
+                })
+                .map(|(i, _)| i)
+        };
+        let line = match comment_start {
+            Some(comment_start) => &line[..comment_start],
+            None => line,
+        }.trim();
+
+        if line.is_empty() {
+            continue
+        }
+
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err(error("expected a `]` at the end of the table name"));
+            }
+            let name = line[1..line.len() - 1].trim();
+
+            let is_identifier = name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+                && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+            if !is_identifier {
+                return Err(error(&format!(
+                    "\"{}\" should be a lowercase Rust identifier, like \"player_idle\"",
+                    name
+                )));
+            }
+
+            if regions.iter().any(|region| region.name == name) {
+                return Err(error(&format!("there is already a region called \"{}\"", name)));
+            }
+
+            regions.push(Region {
+                name: name.to_string(),
+                line: line_number,
+                sheet: None,
+                x: None,
+                y: None,
+                w: None,
+                h: None,
+                frames: None,
+                columns: None,
+                x_advance: None,
+                y_advance: None,
+                widths: None,
+            });
+
+            continue
+        }
+
+        let (key, value) = match line.find('=') {
+            Some(equals) => (line[..equals].trim(), line[equals + 1..].trim()),
+            None => return Err(error("expected a `[table]` or a `key = value` pair")),
+        };
+
+        let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            Value::String(value[1..value.len() - 1].to_string())
+        } else if value == "true" || value == "false" {
+            Value::Boolean(value == "true")
+        } else {
+            match value.parse() {
+                Ok(n) => Value::Integer(n),
+                Err(_) => return Err(error(&format!(
+                    "expected a string, a boolean or a non-negative integer, not {}",
+                    value
+                ))),
+            }
+        };
+
+        let region = match regions.last_mut() {
+            Some(region) => region,
+            None => return Err(error("this needs to be inside a `[region]`")),
+        };
+
+        let duplicate = || error(&format!("`{}` is set more than once", key));
+
+        let slot = match key {
+            "sheet" => {
+                if region.sheet.is_some() {
+                    return Err(duplicate());
+                }
+                match value {
+                    Value::String(s) => region.sheet = Some(s),
+                    _ => return Err(error("`sheet` should be a string")),
+                }
+                continue
+            },
+            "widths" => {
+                if region.widths.is_some() {
+                    return Err(duplicate());
+                }
+                match value {
+                    Value::Boolean(b) => region.widths = Some(b),
+                    _ => return Err(error("`widths` should be `true` or `false`")),
+                }
+                continue
+            },
+            "x" => &mut region.x,
+            "y" => &mut region.y,
+            "w" => &mut region.w,
+            "h" => &mut region.h,
+            "frames" => &mut region.frames,
+            "columns" => &mut region.columns,
+            "x_advance" => &mut region.x_advance,
+            "y_advance" => &mut region.y_advance,
+            _ => return Err(error(&format!("unknown key \"{}\"", key))),
+        };
+
+        if slot.is_some() {
+            return Err(duplicate());
+        }
+
+        match value {
+            Value::Integer(n) => *slot = Some(n),
+            _ => return Err(error(&format!("`{}` should be an integer", key))),
+        }
+    }
+
+    Ok(regions)
+}
+
+fn sheet_id(name: &str) -> Option<SheetId> {
+    SheetId::ALL.iter()
+        .find(|id| format!("{:?}", id) == name)
+        .cloned()
+}
+
+/// How many columns of each frame, from the left, it takes to reach its
+/// rightmost visible pixel. Empty frames are 0 wide.
+fn measure_widths(
+    image: &Image,
+    frame_xys: &[(u32, u32)],
+    w: u32,
+    h: u32,
+) -> Vec<u32> {
+    frame_xys.iter()
+        .map(|&(frame_x, frame_y)| {
+            (0..w).rev()
+                .find(|&column| {
+                    (0..h).any(|row| {
+                        image.get(frame_x + column, frame_y + row) >> 24 != 0
+                    })
+                })
+                .map_or(0, |column| column + 1)
+        })
+        .collect()
+}
+
+/// Writes out a module with the regions in `manifest_filename`.
+pub fn convert(
+    manifest_filename: &str,
+    output_filename: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut text = String::new();
+    File::open(manifest_filename)
+        .map_err(|e| format!("Could not open {}: {}", manifest_filename, e))?
+        .read_to_string(&mut text)?;
+
+    let (output, region_count) = generate(manifest_filename, &text)?;
+
+    let mut file = File::create(output_filename)
+        .map_err(|e| format!("Could not create {}: {}", output_filename, e))?;
+    file.write_all(output.as_bytes())?;
+
+    println!("{} : {} regions", manifest_filename, region_count);
+    println!("overwrote {}", output_filename);
+
+    Ok(())
+}
+
+/// Returns the source of the module for the manifest in `text`, and how many
+/// regions it has.
+fn generate(
+    manifest_filename: &str,
+    text: &str,
+) -> Result<(String, usize), Box<dyn std::error::Error>> {
+    let regions = parse(manifest_filename, text)?;
+
+    let mut output = String::new();
+    output.push_str(
+"//! Named regions of the sprite sheets.
+//!
+//! This file is generated by `tooling/update_gfx` from `assets/sprites.toml`.
+//! Edit that, then run the tool, instead of editing this.
+use crate::sprite::{SheetId, W, H, X, Y, XY};
+"
+    );
+
+    for region in &regions {
+        let error = |message: &str| format!(
+            "{}:{}: [{}] {}",
+            manifest_filename,
+            region.line,
+            region.name,
+            message
+        );
+        macro_rules! required {
+            ($field: ident) => {
+                region.$field.clone().ok_or_else(|| error(concat!(
+                    "needs a value for `",
+                    stringify!($field),
+                    "`"
+                )))?
+            }
+        }
+
+        let sheet_name = required!(sheet);
+        let sheet = match sheet_id(&sheet_name) {
+            Some(id) => sheet_for(id).ok_or_else(|| error(&format!(
+                "no image in `SHEETS` is used for `SheetId::{}`",
+                sheet_name
+            )))?,
+            None => return Err(error(&format!(
+                "\"{}\" is not one of the `SheetId`s: {:?}",
+                sheet_name,
+                SheetId::ALL
+            )).into()),
+        };
+        let (sheet_w, sheet_h) = (sheet.size.0 as u32, sheet.size.1 as u32);
+
+        let (x, y, w, h) = (required!(x), required!(y), required!(w), required!(h));
+        let frames = region.frames.unwrap_or(1);
+        let columns = region.columns.unwrap_or(frames);
+        let x_advance = region.x_advance.unwrap_or(w);
+        let y_advance = region.y_advance.unwrap_or(h);
+
+        if frames == 0 || columns == 0 {
+            return Err(error("needs at least one frame and one column").into());
+        }
+
+        let mut frame_xys = Vec::with_capacity(frames as usize);
+        for i in 0..frames {
+            let frame_x = x + (i % columns) * x_advance;
+            let frame_y = y + (i / columns) * y_advance;
+
+            if frame_x + w > sheet_w || frame_y + h > sheet_h {
+                return Err(error(&format!(
+                    "frame {} at ({}, {}) goes past the edge of the {}x{} sheet",
+                    i,
+                    frame_x,
+                    frame_y,
+                    sheet_w,
+                    sheet_h,
+                )).into());
+            }
+
+            frame_xys.push((frame_x, frame_y));
+        }
+
+        output.push_str(&format!(
+"
+pub mod {name} {{
+    use super::*;
+
+    pub const SHEET: SheetId = SheetId::{sheet};
+    pub const XY: XY = XY {{ x: X({x}), y: Y({y}) }};
+    pub const WIDTH: W = W({w});
+    pub const HEIGHT: H = H({h});
+    pub const COLUMNS: usize = {columns};
+    pub const X_ADVANCE: W = W({x_advance});
+    pub const Y_ADVANCE: H = H({y_advance});
+    pub const FRAMES: [XY; {frames}] = [
+",
+            name = region.name,
+            sheet = sheet_name,
+            x = x,
+            y = y,
+            w = w,
+            h = h,
+            columns = columns,
+            x_advance = x_advance,
+            y_advance = y_advance,
+            frames = frames,
+        ));
+
+        for &(frame_x, frame_y) in &frame_xys {
+            output.push_str(&format!(
+                "        XY {{ x: X({}), y: Y({}) }},\n",
+                frame_x,
+                frame_y
+            ));
+        }
+
+        output.push_str("    ];\n");
+
+        if region.widths == Some(true) {
+            if w > u32::from(u8::MAX) {
+                return Err(error("`widths` needs `w` to fit in a `u8`").into());
+            }
+
+            let image = load(sheet.image)?;
+            if (image.width, image.height) != (sheet_w, sheet_h) {
+                return Err(error(&format!(
+                    "{} is {}x{}, but it should be {}x{}",
+                    sheet.image,
+                    image.width,
+                    image.height,
+                    sheet_w,
+                    sheet_h,
+                )).into());
+            }
+
+            output.push_str(&format!(
+                "    /// How many columns of each frame, from the left, have visible pixels in\n    \
+                /// them, up to the rightmost one. Empty frames are 0 wide.\n    \
+                pub const WIDTHS: [u8; {}] = [\n",
+                frames
+            ));
+
+            let widths = measure_widths(&image, &frame_xys, w, h);
+            for row in widths.chunks(16) {
+                let row: Vec<String> = row.iter().map(|w| w.to_string()).collect();
+                output.push_str(&format!("        {},\n", row.join(", ")));
+            }
+
+            output.push_str("    ];\n");
+        }
+
+        output.push_str("}\n");
+    }
+
+    Ok((output, regions.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(text: &str) -> String {
+        match parse("test.toml", text) {
+            Ok(_) => panic!("expected an error for {:?}", text),
+            Err(e) => e,
+        }
+    }
+
+    fn generate_error(text: &str) -> String {
+        match generate("test.toml", text) {
+            Ok(_) => panic!("expected an error for {:?}", text),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn a_hash_inside_a_string_does_not_start_a_comment() {
+        let regions = parse(
+            "test.toml",
+            "[a] # comment\nsheet = \"Ti#les\" # \"comment\"\n",
+        ).unwrap();
+
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].sheet.as_deref(), Some("Ti#les"));
+    }
+
+    #[test]
+    fn parse_reports_each_kind_of_error_with_its_line() {
+        assert_eq!(
+            parse_error("[a\n"),
+            "test.toml:1: expected a `]` at the end of the table name"
+        );
+        assert_eq!(
+            parse_error("[Player]\n"),
+            "test.toml:1: \"Player\" should be a lowercase Rust identifier, like \"player_idle\""
+        );
+        assert_eq!(
+            parse_error("[a]\n[a]\n"),
+            "test.toml:2: there is already a region called \"a\""
+        );
+        assert_eq!(
+            parse_error("[a]\nx\n"),
+            "test.toml:2: expected a `[table]` or a `key = value` pair"
+        );
+        assert_eq!(
+            parse_error("[a]\nx = -1\n"),
+            "test.toml:2: expected a string, a boolean or a non-negative integer, not -1"
+        );
+        assert_eq!(
+            parse_error("x = 1\n"),
+            "test.toml:1: this needs to be inside a `[region]`"
+        );
+        assert_eq!(
+            parse_error("[a]\nsheet = 1\n"),
+            "test.toml:2: `sheet` should be a string"
+        );
+        assert_eq!(
+            parse_error("[a]\nwidths = 1\n"),
+            "test.toml:2: `widths` should be `true` or `false`"
+        );
+        assert_eq!(
+            parse_error("[a]\nx = \"1\"\n"),
+            "test.toml:2: `x` should be an integer"
+        );
+        assert_eq!(
+            parse_error("[a]\nz = 1\n"),
+            "test.toml:2: unknown key \"z\""
+        );
+    }
+
+    #[test]
+    fn parse_rejects_keys_that_are_set_more_than_once() {
+        assert_eq!(
+            parse_error("[a]\nx = 1\n\nx = 2\n"),
+            "test.toml:4: `x` is set more than once"
+        );
+        assert_eq!(
+            parse_error("[a]\nsheet = \"Tiles\"\nsheet = \"Ui\"\n"),
+            "test.toml:3: `sheet` is set more than once"
+        );
+        assert_eq!(
+            parse_error("[a]\nwidths = true\nwidths = false\n"),
+            "test.toml:3: `widths` is set more than once"
+        );
+
+        // The same key in different regions is fine.
+        assert!(parse("test.toml", "[a]\nx = 1\n[b]\nx = 2\n").is_ok());
+    }
+
+    #[test]
+    fn generate_reports_each_kind_of_error_with_the_region() {
+        assert_eq!(
+            generate_error("[a]\nx = 0\ny = 0\nw = 1\nh = 1\n"),
+            "test.toml:1: [a] needs a value for `sheet`"
+        );
+        assert_eq!(
+            generate_error("[a]\nsheet = \"Tiles\"\ny = 0\nw = 1\nh = 1\n"),
+            "test.toml:1: [a] needs a value for `x`"
+        );
+        assert_eq!(
+            generate_error("[a]\nsheet = \"Nope\"\nx = 0\ny = 0\nw = 1\nh = 1\n"),
+            format!(
+                "test.toml:1: [a] \"Nope\" is not one of the `SheetId`s: {:?}",
+                SheetId::ALL
+            )
+        );
+        assert_eq!(
+            generate_error("[a]\nsheet = \"Tiles\"\nx = 0\ny = 0\nw = 1\nh = 1\nframes = 0\n"),
+            "test.toml:1: [a] needs at least one frame and one column"
+        );
+        assert_eq!(
+            generate_error("[a]\nsheet = \"Tiles\"\nx = 0\ny = 0\nw = 1\nh = 1\ncolumns = 0\n"),
+            "test.toml:1: [a] needs at least one frame and one column"
+        );
+        assert_eq!(
+            generate_error("[a]\nsheet = \"Tiles\"\nx = 0\ny = 0\nw = 256\nh = 1\nwidths = true\n"),
+            "test.toml:1: [a] `widths` needs `w` to fit in a `u8`"
+        );
+    }
+
+    #[test]
+    fn generate_rejects_frames_past_the_edge_of_the_sheet() {
+        // The second row of frames starts at y = 121, so it goes down to 130.
+        let text = "\
+            [a]
+            sheet = \"Font\"
+            x = 0
+            y = 112
+            w = 8
+            h = 9
+            frames = 32
+            columns = 16
+        ";
+
+        assert_eq!(
+            generate_error(text),
+            "test.toml:1: [a] frame 16 at (0, 121) goes past the edge of the 128x128 sheet"
+        );
+    }
+
+    #[test]
+    fn generate_outputs_a_constant_for_each_frame() {
+        let text = "\
+            [two_frames]
+            sheet = \"Ui\"
+            x = 4
+            y = 6
+            w = 10
+            h = 20
+            frames = 3
+            columns = 2
+            x_advance = 12
+        ";
+
+        let (output, region_count) = generate("test.toml", text).unwrap();
+
+        assert_eq!(region_count, 1);
+        assert!(output.contains("
+pub mod two_frames {
+    use super::*;
+
+    pub const SHEET: SheetId = SheetId::Ui;
+    pub const XY: XY = XY { x: X(4), y: Y(6) };
+    pub const WIDTH: W = W(10);
+    pub const HEIGHT: H = H(20);
+    pub const COLUMNS: usize = 2;
+    pub const X_ADVANCE: W = W(12);
+    pub const Y_ADVANCE: H = H(20);
+    pub const FRAMES: [XY; 3] = [
+        XY { x: X(4), y: Y(6) },
+        XY { x: X(16), y: Y(6) },
+        XY { x: X(4), y: Y(26) },
+    ];
+}
+"), "{}", output);
+    }
+
+    #[test]
+    fn the_committed_sprites_module_matches_the_manifest() {
+        let (manifest_filename, module_filename) = super::super::MANIFEST;
+
+        let text = std::fs::read_to_string(manifest_filename).unwrap();
+        let expected = std::fs::read_to_string(module_filename).unwrap();
+
+        let (actual, _) = generate(manifest_filename, &text).unwrap();
+
+        assert!(
+            actual == expected,
+            "{} is out of date. Run `update_gfx` to regenerate it.",
+            module_filename
+        );
+    }
+}